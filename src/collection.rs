@@ -0,0 +1,112 @@
+//! Steam Workshop collections, resolved to their wallpapers.
+
+use std::{
+	fs,
+	process::Command,
+	time::{Duration, SystemTime},
+};
+
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+
+use crate::{Binary, command_runner, config};
+
+pub(crate) static CURL_BIN: Binary = Binary::new("curl");
+
+const API_URL: &str = "https://api.steampowered.com/ISteamRemoteStorage/\
+                       GetCollectionDetails/v1/";
+/// Collections change rarely, so they're only re-fetched once a day.
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Deserialize)]
+struct Response {
+	response: CollectionDetails,
+}
+
+#[derive(Deserialize)]
+struct CollectionDetails {
+	#[serde(default)]
+	collectiondetails: Vec<Collection>,
+}
+
+#[derive(Deserialize)]
+struct Collection {
+	result: u32,
+	#[serde(default)]
+	children: Vec<Child>,
+}
+
+#[derive(Deserialize)]
+struct Child {
+	publishedfileid: String,
+}
+
+/// Returns the wallpaper IDs in a Steam Workshop collection, in order,
+/// fetched again unless `cache` allows the ones from the last day.
+pub fn resolve_collection(
+	id: &str,
+	api_key: Option<&str>,
+	cache: bool,
+) -> Result<Vec<String>> {
+	let cache_dir = config::cache_dir()?.join("collections");
+	let cache_path = cache_dir.join(format!("{}.json", id));
+	let fresh = fs::metadata(&cache_path)
+		.and_then(|metadata| metadata.modified())
+		.is_ok_and(|modified| {
+			SystemTime::now()
+				.duration_since(modified)
+				.is_ok_and(|age| age < CACHE_TTL)
+		});
+	if cache
+		&& fresh
+		&& let Ok(content) = fs::read_to_string(&cache_path)
+		&& let Ok(ids) = serde_json::from_str(&content)
+	{
+		return Ok(ids);
+	}
+
+	let ids = fetch_collection(id, api_key)?;
+	fs::create_dir_all(&cache_dir)?;
+	fs::write(&cache_path, serde_json::to_string(&ids)?)?;
+	Ok(ids)
+}
+
+fn fetch_collection(id: &str, api_key: Option<&str>) -> Result<Vec<String>> {
+	let mut command = Command::new(CURL_BIN.path()?);
+	command.args([
+		"--silent",
+		"--show-error",
+		"--fail",
+		"--data-urlencode",
+		"collectioncount=1",
+		"--data-urlencode",
+		&format!("publishedfileids[0]={}", id),
+	]);
+	// On stdin, since anyone can read the arguments in `/proc`.
+	let output = match api_key {
+		Some(key) => command_runner().output_with_input(
+			command.args(["--data-urlencode", "key@-", API_URL]),
+			key.as_bytes(),
+		)?,
+		None => command_runner().output(command.arg(API_URL))?,
+	};
+	if !output.status.success() {
+		return Err(anyhow!(
+			"couldn't fetch collection {}: {}",
+			id,
+			String::from_utf8_lossy(&output.stderr).trim()
+		));
+	}
+
+	let response: Response = serde_json::from_slice(&output.stdout)
+		.map_err(|e| anyhow!("bad response for collection {}: {}", id, e))?;
+	match response.response.collectiondetails.first() {
+		// 1 is Steam's `k_EResultOK`.
+		Some(collection) if collection.result == 1 => Ok(collection
+			.children
+			.iter()
+			.map(|child| child.publishedfileid.clone())
+			.collect()),
+		_ => Err(anyhow!("{} isn't a Workshop collection", id)),
+	}
+}
@@ -1,87 +1,194 @@
+//! Proton versions and the compatibility tool Steam runs the engine with.
+
 use std::{
-	path::PathBuf,
+	fs,
+	path::{Path, PathBuf},
 	process::{Command, ExitStatus},
 	sync::LazyLock,
+	time::Duration,
 };
 
 use anyhow::{Result, anyhow};
 use heck::ToSnakeCase;
+use log::{debug, warn};
 use pomsky::options::CompileOptions;
 use regex::Regex;
 
-use crate::{COMMON, STEAM_BIN, STEAM_PATH};
+use crate::{
+	STEAM_BIN, STEAM_PATH, command_runner, common_dir, config, dry_run,
+	is_dry_run, is_executable, vdf::Vdf, wait_until,
+};
 
+/// [`compatibilitytools_dir`] of [`STEAM_PATH`].
 pub static COMPATIBILITYTOOLS_D: LazyLock<PathBuf> =
-	LazyLock::new(|| STEAM_PATH.join("compatibilitytools.d"));
+	LazyLock::new(|| compatibilitytools_dir(&STEAM_PATH));
+
+/// Where custom tools like GE-Proton are installed.
+pub fn compatibilitytools_dir(root: &Path) -> PathBuf {
+	root.join("compatibilitytools.d")
+}
 
+/// How long Steam gets to write a compat tool change to its config.
+const APPLY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A Proton version or custom compatibility tool.
 #[derive(Debug)]
 pub struct SteamCompat {
+	/// The folder name, which isn't always what Steam calls it, see
+	/// [`SteamCompat::internal_name`].
 	pub name: String,
+	/// The tool's folder.
 	pub path: PathBuf,
+	/// Valve's Proton rather than a custom tool like GE-Proton.
 	pub builtin: bool,
 }
+/// Builtin versions Steam names after a word instead of the version number.
+const NAMED_VERSIONS: [(&str, &str); 2] = [
+	("Proton Experimental", "proton_experimental"),
+	("Proton Hotfix", "proton_hotfix"),
+];
+
+/// Where tools keep their `proton` launcher, relative to the tool folder.
+const PROTON_SCRIPTS: [&str; 2] = ["proton", "files/bin/proton"];
+
 impl SteamCompat {
-	pub fn internal_name(&self) -> String {
-		if self.builtin {
-			// Steam has internal names for Proton versions.
-			// Always prefixed with `proton_`.
-			// If the version is a word it's `word`.
-			// If the version is a number it's horrible.
-			// `Proton 10.0` -> `proton_10`
-			// `Proton 9.0 (Beta) -> `proton_9`
-			// `Proton 4.11` -> `proton_411`
-			// This may break for Steam eventually...
-			let snake = self.name.to_snake_case();
-
-			let re = Regex::new(
-				&pomsky::Expr::parse_and_compile(
-					include_str!("./internal.pom"),
-					CompileOptions::default(),
+	/// The tool's `proton` launcher, the first of the known script names
+	/// that exists.
+	pub fn proton_script(&self) -> Result<PathBuf> {
+		PROTON_SCRIPTS
+			.iter()
+			.map(|script| self.path.join(script))
+			.find(|script| script.is_file())
+			.ok_or_else(|| {
+				anyhow!(
+					"no proton launcher in {:?}, looked for: {}",
+					self.path,
+					PROTON_SCRIPTS.join(", ")
 				)
-				.0
-				.unwrap(),
+			})
+	}
+
+	/// The tool in the folder `path`, wherever it is. Custom tools are told
+	/// apart from Valve's Proton by their `compatibilitytool.vdf`.
+	pub fn from_path(path: &Path) -> Result<SteamCompat> {
+		let name = path
+			.file_name()
+			.filter(|_| path.is_dir())
+			.ok_or_else(|| anyhow!("{:?} isn't a Proton folder", path))?;
+		let sc = SteamCompat {
+			name: name.to_string_lossy().to_string(),
+			path: path.to_path_buf(),
+			builtin: !path.join("compatibilitytool.vdf").exists(),
+		};
+		let script = sc.proton_script()?;
+		if !is_executable(&script) {
+			return Err(anyhow!("{:?} isn't executable", script));
+		}
+		Ok(sc)
+	}
+
+	/// The name Steam knows the tool by, which `+app_change_compat_tool`
+	/// needs.
+	pub fn internal_name(&self) -> Result<String> {
+		if !self.builtin {
+			return Ok(self
+				.declared_name()
+				.unwrap_or_else(|| self.name.clone()));
+		}
+
+		if let Some((_, internal)) = NAMED_VERSIONS
+			.iter()
+			.find(|(name, _)| name.eq_ignore_ascii_case(&self.name))
+		{
+			return Ok(internal.to_string());
+		}
+		// `Proton EasyAntiCheat Runtime` and friends live next to Proton, but
+		// games load them, they can't run anything themselves.
+		if self.name.to_lowercase().ends_with("runtime") {
+			return Err(anyhow!(
+				"{:?} is a runtime for games, not a Proton version",
+				self.name
+			));
+		}
+
+		// Steam has internal names for Proton versions.
+		// Always prefixed with `proton_`.
+		// If the version is a word it's `word`.
+		// If the version is a number it's horrible.
+		// `Proton 10.0` -> `proton_10`
+		// `Proton 9.0 (Beta) -> `proton_9`
+		// `Proton 4.11` -> `proton_411`
+		// `Proton 6.3` -> `proton_63`
+		// This may break for Steam eventually...
+		let snake = self.name.to_snake_case();
+
+		let re = Regex::new(
+			&pomsky::Expr::parse_and_compile(
+				include_str!("./internal.pom"),
+				CompileOptions::default(),
 			)
-			.unwrap();
+			.0
+			.unwrap(),
+		)
+		.unwrap();
 
-			if let Some(caps) = re.captures(&snake) {
-				if let Some(matched) = caps.name("name") {
-					return matched
-						.as_str()
-						.to_string()
-						// Diabolical way to remove extra underscores.
-						.replace("proton_", "proton-")
-						.replace("_", "")
-						.replace("proton-", "proton_");
-				}
-			}
+		if let Some(caps) = re.captures(&snake)
+			&& let Some(matched) = caps.name("name")
+		{
+			return Ok(matched
+				.as_str()
+				.to_string()
+				// Diabolical way to remove extra underscores.
+				.replace("proton_", "proton-")
+				.replace("_", "")
+				.replace("proton-", "proton_"));
+		}
 
-			eprintln!(
-				"Getting to this code branch is very bad... Continuing anyway!!"
-			);
+		Err(anyhow!(
+			"don't know Steam's internal name for {:?}",
+			self.name
+		))
+	}
 
-			snake
-		} else {
-			self.name.clone()
+	/// The name a custom tool registers in its `compatibilitytool.vdf`,
+	/// which is what Steam knows it by, not the folder name.
+	fn declared_name(&self) -> Option<String> {
+		let vdf_path = self.path.join("compatibilitytool.vdf");
+		if !vdf_path.exists() {
+			return None;
 		}
+		let vdf = Vdf::from_file(&vdf_path)
+			.inspect_err(|e| warn!("Couldn't read the tool's name: {}", e))
+			.ok()?;
+		// "compatibilitytools" { "compat_tools" { "GE-Proton9-20" { ... } } }
+		let tools = vdf.get_path(&["compatibilitytools", "compat_tools"])?;
+		tools.entries().first().map(|(name, _)| name.clone())
+	}
+
+	/// The tool in the folder `name` of either tool folder, preferring the
+	/// custom one in `compatibilitytools.d`.
+	pub fn from_name(name: &str) -> Option<SteamCompat> {
+		SteamCompat::from_name_in(&STEAM_PATH, name)
 	}
 
-	pub fn from_name(name: &String) -> Option<SteamCompat> {
-		let common_dir = COMMON.join(name);
-		let d_dir = COMPATIBILITYTOOLS_D.join(name);
+	/// [`SteamCompat::from_name`] for the Steam installed at `root`.
+	pub fn from_name_in(root: &Path, name: &str) -> Option<SteamCompat> {
+		let common_dir = common_dir(root).join(name);
+		let d_dir = compatibilitytools_dir(root).join(name);
 
 		match (common_dir.exists(), d_dir.exists()) {
 			(true, true) => Some(SteamCompat {
-				name: name.clone(),
+				name: name.to_string(),
 				path: d_dir,
 				builtin: false,
 			}),
 			(true, false) => Some(SteamCompat {
-				name: name.clone(),
+				name: name.to_string(),
 				path: common_dir,
 				builtin: true,
 			}),
 			(false, true) => Some(SteamCompat {
-				name: name.clone(),
+				name: name.to_string(),
 				path: d_dir,
 				builtin: false,
 			}),
@@ -89,14 +196,434 @@ impl SteamCompat {
 		}
 	}
 
-	/// https://developer.valvesoftware.com/wiki/Command_line_options#Command-Line_Parameters
-	/// https://gist.github.com/davispuh/6600880
+	/// Like [`SteamCompat::from_name`], but also takes Steam's internal name
+	/// of a tool, picks the only tool starting with `name` when there's no
+	/// exact match, and suggests close ones otherwise.
+	pub fn find(name: &str) -> Result<SteamCompat> {
+		SteamCompat::find_in(&STEAM_PATH, name)
+	}
+
+	/// [`SteamCompat::find`] for the Steam installed at `root`.
+	pub fn find_in(root: &Path, name: &str) -> Result<SteamCompat> {
+		if let Some(sc) = SteamCompat::from_name_in(root, name) {
+			return Ok(sc);
+		}
+
+		let tools = installed_tools_in(root);
+		// `proton_9` is both `Proton 9.0` and `Proton 9.0 (Beta)`.
+		let mut internal = tools
+			.iter()
+			.filter_map(|tool| SteamCompat::from_name_in(root, tool))
+			.filter(|sc| {
+				sc.internal_name()
+					.is_ok_and(|internal| internal.eq_ignore_ascii_case(name))
+			})
+			.collect::<Vec<_>>();
+		if !internal.is_empty() {
+			let sc = internal.remove(0);
+			if !internal.is_empty() {
+				warn!(
+					"{:?} is Steam's name for {:?} and {}, using {:?}",
+					name,
+					sc.name,
+					internal
+						.iter()
+						.map(|other| format!("{:?}", other.name))
+						.collect::<Vec<_>>()
+						.join(", "),
+					sc.name
+				);
+			}
+			return Ok(sc);
+		}
+
+		let lowercase = name.to_lowercase();
+		let prefixed = tools
+			.iter()
+			.filter(|tool| tool.to_lowercase().starts_with(&lowercase))
+			.collect::<Vec<_>>();
+		if let [tool] = prefixed.as_slice()
+			&& let Some(sc) = SteamCompat::from_name_in(root, tool)
+		{
+			warn!("Using {:?} for {:?}", tool, name);
+			return Ok(sc);
+		}
+
+		let mut suggestions = prefixed;
+		if suggestions.is_empty() {
+			let mut close = tools
+				.iter()
+				.map(|tool| {
+					(levenshtein(&lowercase, &tool.to_lowercase()), tool)
+				})
+				.filter(|(distance, _)| *distance <= 3)
+				.collect::<Vec<_>>();
+			close.sort();
+			suggestions = close.into_iter().map(|(_, tool)| tool).collect();
+		}
+		if suggestions.is_empty() {
+			return Err(anyhow!(
+				"Proton folder not found: {:?}, installed: {:?}",
+				name,
+				tools
+			));
+		}
+		Err(anyhow!(
+			"Proton folder not found: {:?}, did you mean {}?",
+			name,
+			suggestions
+				.iter()
+				.map(|tool| format!("{:?}", tool))
+				.collect::<Vec<_>>()
+				.join(" or ")
+		))
+	}
+
+	/// Remembers the tool Steam used before the first switch so
+	/// [`reset_compat_tool`] can restore it.
 	pub fn apply_to_game(&self, id: u32) -> Result<ExitStatus> {
-		Command::new(STEAM_BIN.as_path())
-			.arg("+app_change_compat_tool")
-			.arg(id.to_string())
-			.arg(self.internal_name())
-			.status()
-			.map_err(|e| anyhow!("{}", e))
+		self.apply_to_game_in(&STEAM_PATH, id)
+	}
+
+	/// [`SteamCompat::apply_to_game`] for the Steam installed at `root`.
+	pub fn apply_to_game_in(
+		&self,
+		root: &Path,
+		id: u32,
+	) -> Result<ExitStatus> {
+		let name = self.internal_name()?;
+		// Later runs would save the tool an earlier one switched to, and an
+		// empty file stands for Steam's default. Nothing changes when it's
+		// already `name`, so that's left for the run that does switch.
+		let previous = current_compat_tool_in(root, id).unwrap_or_default();
+		let path = previous_tool_path(id)?;
+		if previous != name && !path.exists() && !is_dry_run() {
+			if let Some(parent) = path.parent() {
+				fs::create_dir_all(parent)?;
+			}
+			fs::write(&path, &previous)?;
+			debug!("Saved previous compat tool {:?} to {:?}", previous, path);
+		}
+
+		// Steam can report success and still ignore the change, so it's read
+		// back and asked once more.
+		let applied =
+			|| current_compat_tool_in(root, id).as_deref() == Some(&name);
+		let mut status = set_compat_tool(id, &name)?;
+		if is_dry_run() || wait_until(applied, APPLY_TIMEOUT) {
+			return Ok(status);
+		}
+		debug!("Steam didn't switch to {:?} yet, asking again", name);
+		status = set_compat_tool(id, &name)?;
+		if !wait_until(applied, APPLY_TIMEOUT) {
+			warn!(
+				"Steam still lists {:?} instead of {:?} for app {}, the \
+				 wallpapers may run with the wrong Proton, check the \
+				 compatibility setting in Steam",
+				current_compat_tool_in(root, id)
+					.unwrap_or_else(|| "nothing".into()),
+				name,
+				id
+			);
+		}
+		Ok(status)
+	}
+}
+
+/// https://developer.valvesoftware.com/wiki/Command_line_options#Command-Line_Parameters
+/// https://gist.github.com/davispuh/6600880
+fn set_compat_tool(id: u32, name: &str) -> Result<ExitStatus> {
+	let mut command = Command::new(STEAM_BIN.path()?);
+	command
+		.arg("+app_change_compat_tool")
+		.arg(id.to_string())
+		.arg(name);
+	if dry_run(&command) {
+		return Ok(ExitStatus::default());
+	}
+	command_runner()
+		.status(&mut command)
+		.map_err(|e| anyhow!("{}", e))
+}
+
+/// The internal name of the tool Steam runs `id` with, from its
+/// `config/config.vdf`.
+pub fn current_compat_tool(id: u32) -> Option<String> {
+	current_compat_tool_in(&STEAM_PATH, id)
+}
+
+/// [`current_compat_tool`] for the Steam installed at `root`.
+pub fn current_compat_tool_in(root: &Path, id: u32) -> Option<String> {
+	let vdf = Vdf::from_file(&root.join("config/config.vdf")).ok()?;
+	vdf.get_path(&[
+		"InstallConfigStore",
+		"Software",
+		"Valve",
+		"Steam",
+		"CompatToolMapping",
+		&id.to_string(),
+		"name",
+	])?
+	.as_str()
+	.filter(|name| !name.is_empty())
+	.map(str::to_string)
+}
+
+fn previous_tool_path(id: u32) -> Result<PathBuf> {
+	Ok(config::state_dir()?.join(format!("previous_compat_tool_{}", id)))
+}
+
+/// Sets `id` back to the tool it used before [`SteamCompat::apply_to_game`]
+/// changed it, returning that tool's name, empty for Steam's default.
+pub fn reset_compat_tool(id: u32) -> Result<Option<String>> {
+	let path = previous_tool_path(id)?;
+	let Ok(previous) = fs::read_to_string(&path) else {
+		return Ok(None);
+	};
+	let previous = previous.trim().to_string();
+	set_compat_tool(id, &previous)?;
+	if !is_dry_run() {
+		fs::remove_file(&path)?;
+	}
+	Ok(Some(previous))
+}
+
+/// Folder names of every Proton version and custom compatibility tool,
+/// sorted.
+pub fn installed_tools() -> Vec<String> {
+	installed_tools_in(&STEAM_PATH)
+}
+
+/// [`installed_tools`] for the Steam installed at `root`.
+pub fn installed_tools_in(root: &Path) -> Vec<String> {
+	let mut tools = Vec::new();
+	for (dir, needs_proton) in [
+		(common_dir(root), true),
+		(compatibilitytools_dir(root), false),
+	] {
+		let Ok(entries) = fs::read_dir(dir) else {
+			continue;
+		};
+		for entry in entries.flatten() {
+			// Games live next to Proton in `common`.
+			if needs_proton
+				&& !PROTON_SCRIPTS
+					.iter()
+					.any(|script| entry.path().join(script).is_file())
+			{
+				continue;
+			}
+			let name = entry.file_name().to_string_lossy().to_string();
+			if entry.path().is_dir() && !tools.contains(&name) {
+				tools.push(name);
+			}
+		}
+	}
+	tools.sort();
+	tools
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+	let b = b.chars().collect::<Vec<_>>();
+	let mut row = (0..=b.len()).collect::<Vec<_>>();
+	for (i, a) in a.chars().enumerate() {
+		let mut previous = row[0];
+		row[0] = i + 1;
+		for (j, &b) in b.iter().enumerate() {
+			let substitution = previous + usize::from(a != b);
+			previous = row[j + 1];
+			row[j + 1] = substitution.min(row[j] + 1).min(previous + 1);
+		}
+	}
+	row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{env, fs::Permissions, os::unix::fs::PermissionsExt};
+
+	use super::*;
+	use crate::runner::tests::RecordingRunner;
+
+	fn builtin(name: &str) -> SteamCompat {
+		SteamCompat {
+			name: name.to_string(),
+			path: PathBuf::new(),
+			builtin: true,
+		}
+	}
+
+	#[test]
+	fn internal_names() {
+		for (name, internal) in [
+			("Proton 10.0", "proton_10"),
+			("Proton 9.0 (Beta)", "proton_9"),
+			("Proton 8.0", "proton_8"),
+			("Proton 4.11", "proton_411"),
+			("Proton 6.3", "proton_63"),
+			("Proton 3.7", "proton_37"),
+			("Proton Experimental", "proton_experimental"),
+			("Proton Hotfix", "proton_hotfix"),
+		] {
+			assert_eq!(
+				builtin(name).internal_name().unwrap(),
+				internal,
+				"{}",
+				name
+			);
+		}
+	}
+
+	#[test]
+	fn runtimes_are_rejected() {
+		assert!(
+			builtin("Proton EasyAntiCheat Runtime")
+				.internal_name()
+				.is_err()
+		);
+		assert!(builtin("Proton BattlEye Runtime").internal_name().is_err());
+	}
+
+	#[test]
+	fn apply_to_game_runs_steam() {
+		let runner = RecordingRunner::install();
+		let root = env::temp_dir()
+			.join(format!("wex_test_apply_to_game_{}", std::process::id()));
+		fs::create_dir_all(root.join("config")).unwrap();
+		// Already switched, so nothing is saved or asked twice.
+		fs::write(
+			root.join("config/config.vdf"),
+			r#""InstallConfigStore" { "Software" { "Valve" { "Steam" {
+				"CompatToolMapping" { "431960" { "name" "proton_10" } }
+			} } } }"#,
+		)
+		.unwrap();
+
+		builtin("Proton 10.0")
+			.apply_to_game_in(&root, 431960)
+			.unwrap();
+		let _ = fs::remove_dir_all(&root);
+		assert_eq!(
+			runner.commands(),
+			[["steam", "+app_change_compat_tool", "431960", "proton_10"]]
+		);
+	}
+
+	#[test]
+	fn proton_script_locations() {
+		let root = env::temp_dir()
+			.join(format!("wex_test_proton_script_{}", std::process::id()));
+		let sc = SteamCompat {
+			name: "Nested".to_string(),
+			path: root.clone(),
+			builtin: false,
+		};
+		fs::create_dir_all(root.join("files/bin")).unwrap();
+		assert!(sc.proton_script().is_err());
+
+		fs::write(root.join("files/bin/proton"), "").unwrap();
+		assert_eq!(
+			sc.proton_script().unwrap(),
+			root.join("files/bin/proton")
+		);
+		// The usual place wins.
+		fs::write(root.join("proton"), "").unwrap();
+		assert_eq!(sc.proton_script().unwrap(), root.join("proton"));
+		let _ = fs::remove_dir_all(&root);
+	}
+
+	#[test]
+	fn from_path_checks_the_launcher() {
+		let root = env::temp_dir()
+			.join(format!("wex_test_from_path_{}", std::process::id()))
+			.join("My Proton");
+		fs::create_dir_all(&root).unwrap();
+		fs::write(root.join("proton"), "").unwrap();
+		// Not executable yet.
+		assert!(SteamCompat::from_path(&root).is_err());
+
+		fs::set_permissions(
+			root.join("proton"),
+			Permissions::from_mode(0o755),
+		)
+		.unwrap();
+		let sc = SteamCompat::from_path(&root).unwrap();
+		assert_eq!(sc.name, "My Proton");
+		assert!(sc.builtin);
+		fs::write(root.join("compatibilitytool.vdf"), "").unwrap();
+		assert!(!SteamCompat::from_path(&root).unwrap().builtin);
+		let _ = fs::remove_dir_all(root.parent().unwrap());
+	}
+
+	#[test]
+	fn find_by_internal_name() {
+		let root = env::temp_dir()
+			.join(format!("wex_test_find_internal_{}", std::process::id()));
+		for dir in [
+			common_dir(&root).join("Proton 9.0"),
+			common_dir(&root).join("Proton 9.0 (Beta)"),
+			compatibilitytools_dir(&root).join("GE-Proton9-20"),
+		] {
+			fs::create_dir_all(&dir).unwrap();
+			fs::write(dir.join("proton"), "").unwrap();
+		}
+
+		assert_eq!(
+			SteamCompat::find_in(&root, "proton_9").unwrap().name,
+			"Proton 9.0"
+		);
+		let ge = SteamCompat::find_in(&root, "ge-proton9-20").unwrap();
+		assert_eq!(
+			ge.path,
+			compatibilitytools_dir(&root).join("GE-Proton9-20")
+		);
+		assert!(SteamCompat::find_in(&root, "proton_8").is_err());
+		let _ = fs::remove_dir_all(&root);
+	}
+
+	#[test]
+	fn custom_tool_declares_its_name() {
+		let root = env::temp_dir()
+			.join(format!("wex_test_declared_name_{}", std::process::id()));
+		let dir = compatibilitytools_dir(&root).join("my-ge");
+		fs::create_dir_all(&dir).unwrap();
+		fs::write(dir.join("proton"), "").unwrap();
+		fs::write(
+			dir.join("compatibilitytool.vdf"),
+			r#"// Added by the installer
+			"compatibilitytools"
+			{
+				"compat_tools"
+				{
+					"GE-Proton9-20"
+					{
+						"install_path" "."
+						"display_name" "GE-Proton9-20 \"custom\""
+						"from_oslist" "windows"
+						"to_oslist" "linux"
+					}
+				}
+			}"#,
+		)
+		.unwrap();
+
+		let sc = SteamCompat::from_name_in(&root, "my-ge").unwrap();
+		assert_eq!(sc.internal_name().unwrap(), "GE-Proton9-20");
+		// Steam's name finds the folder too.
+		assert_eq!(
+			SteamCompat::find_in(&root, "GE-Proton9-20").unwrap().path,
+			dir
+		);
+		let _ = fs::remove_dir_all(&root);
+	}
+
+	#[test]
+	fn custom_tool_without_vdf_uses_folder_name() {
+		let sc = SteamCompat {
+			name: "GE-Proton9-20".to_string(),
+			path: PathBuf::from("/nonexistent"),
+			builtin: false,
+		};
+		assert_eq!(sc.internal_name().unwrap(), "GE-Proton9-20");
 	}
 }
@@ -0,0 +1,106 @@
+//! The config file and the folders for cached and saved state.
+
+use std::{collections::HashMap, fs, path::PathBuf, sync::LazyLock};
+
+use anyhow::{Result, anyhow};
+use directories::ProjectDirs;
+use serde::Deserialize;
+
+// Respects the XDG variables, `dirs::home_dir` is only used for Steam.
+static PROJECT_DIRS: LazyLock<Option<ProjectDirs>> =
+	LazyLock::new(|| ProjectDirs::from("", "", "wallpaper_engine_xwayland"));
+
+fn project_dirs() -> Result<&'static ProjectDirs> {
+	PROJECT_DIRS.as_ref().ok_or_else(|| {
+		anyhow!(
+			"no home folder for the config and cache, set HOME or the \
+			 XDG_*_HOME variables"
+		)
+	})
+}
+
+/// `config.toml` in the XDG config folder.
+pub fn config_path() -> Result<PathBuf> {
+	Ok(project_dirs()?.config_dir().join("config.toml"))
+}
+
+/// The XDG cache folder, for previews and collections.
+pub fn cache_dir() -> Result<PathBuf> {
+	Ok(project_dirs()?.cache_dir().to_path_buf())
+}
+
+/// The XDG state folder, or the data one where there's none.
+pub fn state_dir() -> Result<PathBuf> {
+	let dirs = project_dirs()?;
+	Ok(dirs
+		.state_dir()
+		.unwrap_or(dirs.data_local_dir())
+		.to_path_buf())
+}
+
+/// Defaults for the CLI flags, which override them when present.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+	/// Like `--proton-version`.
+	pub proton_version: Option<String>,
+	/// Like `--arch`.
+	pub arch: Option<String>,
+	/// Like `--width`.
+	pub width: Option<u32>,
+	/// Like `--height`.
+	pub height: Option<u32>,
+	/// Like `--wallpaper-ids`, `ID[:OUTPUT]` each.
+	pub wallpaper_ids: Vec<String>,
+	/// Like `--fps`.
+	pub fps: Option<u32>,
+	/// Like `--volume`.
+	pub volume: Option<u8>,
+	/// Used when resolving Workshop collections.
+	pub steam_api_key: Option<String>,
+	/// Named sets of wallpapers for `wex apply`.
+	pub profiles: HashMap<String, Profile>,
+}
+
+/// Replaces the matching top-level settings when applied.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+	/// Replaces [`Config::wallpaper_ids`] unless empty.
+	pub wallpaper_ids: Vec<String>,
+	/// Replaces [`Config::fps`] when set.
+	pub fps: Option<u32>,
+	/// Replaces [`Config::volume`] when set.
+	pub volume: Option<u8>,
+}
+
+impl Config {
+	/// Returns the default config if the file doesn't exist.
+	pub fn load() -> Result<Config> {
+		let path = config_path()?;
+		if !path.exists() {
+			return Ok(Config::default());
+		}
+		let content = fs::read_to_string(&path)?;
+		toml::from_str(&content)
+			.map_err(|e| anyhow!("invalid config {:?}: {}", path, e))
+	}
+
+	/// Uses the settings of the profile called `name` where it has them.
+	pub fn apply_profile(mut self, name: &str) -> Result<Config> {
+		let Some(profile) = self.profiles.remove(name) else {
+			let mut names = self.profiles.keys().collect::<Vec<_>>();
+			names.sort();
+			return Err(anyhow!(
+				"no profile {:?} in {:?}, defined: {:?}",
+				name,
+				config_path()?,
+				names
+			));
+		};
+		self.wallpaper_ids = profile.wallpaper_ids;
+		self.fps = profile.fps.or(self.fps);
+		self.volume = profile.volume.or(self.volume);
+		Ok(self)
+	}
+}
@@ -0,0 +1,2519 @@
+//! Runs Wallpaper Engine wallpapers in XWayland windows through Proton.
+//!
+//! The `wex` binary is a thin CLI over [`launch`], [`list`], [`info()`] and
+//! [`stop`].
+
+#![warn(missing_docs)]
+
+use std::{
+	collections::{VecDeque, hash_map::RandomState},
+	env,
+	ffi::OsString,
+	fs::{self, OpenOptions},
+	hash::BuildHasher,
+	io::{self, Write},
+	os::unix::{fs::PermissionsExt, process::CommandExt},
+	path::{Path, PathBuf},
+	process::{Command, Stdio},
+	rc::Rc,
+	str::FromStr,
+	sync::{
+		LazyLock, Once, OnceLock,
+		atomic::{AtomicBool, Ordering},
+	},
+	thread,
+	time::{Duration, Instant},
+};
+
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use log::{debug, info, warn};
+use preview::{Previewer, show_preview};
+use project::{ProjectInfo, WallpaperType, read_project_info};
+use window::Layer;
+
+pub mod collection;
+pub mod compat;
+pub mod config;
+pub mod library;
+pub mod output;
+pub mod power;
+pub mod preview;
+pub mod project;
+pub mod runner;
+pub mod vdf;
+pub mod window;
+
+pub use compat::SteamCompat;
+pub use runner::{CommandRunner, command_runner, set_command_runner};
+
+/// Wallpaper Engine's Steam app ID.
+pub const WALLPAPER_ENGINE_ID: u32 = 431960;
+
+static APP_ID: OnceLock<u32> = OnceLock::new();
+
+/// The app ID everything uses, [`WALLPAPER_ENGINE_ID`] unless
+/// [`set_app_id`] changed it.
+pub fn app_id() -> u32 {
+	*APP_ID.get_or_init(|| WALLPAPER_ENGINE_ID)
+}
+
+/// Overrides the app ID, which only works before anything used it.
+pub fn set_app_id(id: u32) -> Result<()> {
+	APP_ID.set(id).map_err(|_| {
+		anyhow!("the app ID was already used, set it earlier")
+	})?;
+	Ok(())
+}
+
+static COMPAT_DATA_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// The prefix everything uses, [`compatdata_dir`] of [`STEAM_PATH`] unless
+/// [`set_compat_data_path`] changed it.
+pub fn compat_data_path() -> &'static Path {
+	COMPAT_DATA_PATH.get_or_init(|| compatdata_dir(&STEAM_PATH))
+}
+
+/// Overrides the prefix, which only works before anything used it.
+pub fn set_compat_data_path(path: PathBuf) -> Result<()> {
+	if !path.is_dir() {
+		return Err(anyhow!("compat data path {:?} isn't a folder", path));
+	}
+	COMPAT_DATA_PATH.set(path).map_err(|_| {
+		anyhow!("the compat data path was already used, set it earlier")
+	})?;
+	Ok(())
+}
+
+/// Expands a leading `~` to the home folder and makes relative paths
+/// absolute, for paths users type.
+pub fn expand_path(path: &Path) -> PathBuf {
+	let path = match (path.strip_prefix("~"), dirs::home_dir()) {
+		(Ok(rest), Some(home)) => home.join(rest),
+		_ => path.to_path_buf(),
+	};
+	if path.is_relative()
+		&& let Ok(cwd) = env::current_dir()
+	{
+		return cwd.join(path);
+	}
+	path
+}
+
+/// Where Steam might be installed, in order of preference.
+///
+/// `STEAM_ROOT` overrides the native and Flatpak locations.
+pub fn steam_path_candidates() -> Vec<PathBuf> {
+	let mut candidates = Vec::new();
+	if let Some(root) = env::var_os("STEAM_ROOT") {
+		candidates.push(expand_path(Path::new(&root)));
+	}
+	if let Some(home) = dirs::home_dir() {
+		candidates.push(home.join(".steam/steam"));
+		candidates
+			.push(home.join(".var/app/com.valvesoftware.Steam/.steam/steam"));
+	}
+	candidates
+}
+
+/// The first existing Steam root, or the first candidate if none exist.
+pub static STEAM_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
+	let candidates = steam_path_candidates();
+	candidates
+		.iter()
+		.find(|path| path.exists())
+		.or(candidates.first())
+		.cloned()
+		.unwrap_or_default()
+});
+/// [`steamapps_dir`] of the detected Steam root.
+pub static STEAMAPPS: LazyLock<PathBuf> =
+	LazyLock::new(|| steamapps_dir(&STEAM_PATH));
+/// [`common_dir`] of the detected Steam root.
+pub static COMMON: LazyLock<PathBuf> =
+	LazyLock::new(|| common_dir(&STEAM_PATH));
+/// Wallpaper Engine's Proton prefix, see [`compat_data_path`].
+pub static COMPATDATA_PATH: LazyLock<PathBuf> =
+	LazyLock::new(|| compat_data_path().to_path_buf());
+/// [`workshop_content_dir`] of the detected Steam root, other libraries
+/// have their own, see [`library::workshop_content_dirs`].
+pub static WORKSHOP_CONTENT_PATH: LazyLock<PathBuf> =
+	LazyLock::new(|| workshop_content_dir(&STEAM_PATH));
+
+// The statics above use these with the detected root, tests can pass their
+// own.
+
+/// `steamapps` of a Steam root or library.
+pub fn steamapps_dir(root: &Path) -> PathBuf {
+	root.join("steamapps")
+}
+
+/// Where games and Proton versions are installed.
+pub fn common_dir(root: &Path) -> PathBuf {
+	steamapps_dir(root).join("common")
+}
+
+/// Wallpaper Engine's Proton prefix.
+pub fn compatdata_dir(root: &Path) -> PathBuf {
+	steamapps_dir(root)
+		.join("compatdata")
+		.join(app_id().to_string())
+}
+
+/// Wallpaper Engine's workshop downloads.
+pub fn workshop_content_dir(root: &Path) -> PathBuf {
+	steamapps_dir(root)
+		.join("workshop/content")
+		.join(app_id().to_string())
+}
+
+/// Wallpaper Engine's install folder in whichever library has it, or where
+/// it would be in the detected root.
+pub static WALLPAPER_ENGINE_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
+	library::find_wallpaper_engine()
+		.unwrap_or_else(|| COMMON.join("wallpaper_engine"))
+});
+
+/// An external program looked up on PATH the first time it's needed, through
+/// the [`CommandRunner`].
+pub struct Binary {
+	/// What it's called on PATH.
+	pub name: &'static str,
+	path: OnceLock<Option<PathBuf>>,
+}
+impl Binary {
+	const fn new(name: &'static str) -> Binary {
+		Binary {
+			name,
+			path: OnceLock::new(),
+		}
+	}
+
+	/// Uses `path` instead of looking it up, which only works before
+	/// anything used it.
+	fn set_path(&self, path: PathBuf) -> Result<()> {
+		if !is_executable(&path) {
+			return Err(anyhow!("{:?} isn't an executable file", path));
+		}
+		self.path.set(Some(path)).map_err(|_| {
+			anyhow!(
+				"the path of `{}` was already used, set it earlier",
+				self.name
+			)
+		})?;
+		Ok(())
+	}
+
+	/// Where it was found, or an error saying to install it.
+	pub fn path(&self) -> Result<&Path> {
+		self.path
+			.get_or_init(|| command_runner().which(self.name))
+			.as_deref()
+			.ok_or_else(|| {
+				anyhow!(
+					"`{}` not found, install it and make sure it's on \
+					 your PATH",
+					self.name
+				)
+			})
+	}
+}
+
+pub(crate) static STEAM_BIN: Binary = Binary::new("steam");
+
+/// Runs `path` as Steam instead of the `steam` on PATH.
+pub fn set_steam_bin(path: PathBuf) -> Result<()> {
+	STEAM_BIN.set_path(path)
+}
+
+/// Whether `path` is a file with any execute bit set.
+pub(crate) fn is_executable(path: &Path) -> bool {
+	fs::metadata(path).is_ok_and(|metadata| {
+		metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+	})
+}
+pub(crate) static CHAFA_BIN: Binary = Binary::new("chafa");
+pub(crate) static XDOTOOL_BIN: Binary = Binary::new("xdotool");
+
+const DEFAULT_RESOLUTION: (u32, u32) = (1920, 1080);
+
+/// How launched wallpapers are reported on stdout.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+	/// Titles, descriptions and previews.
+	Text,
+	/// One JSON object per line.
+	Json,
+}
+
+/// How wallpapers are handed to Wallpaper Engine.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum SpawnMode {
+	/// Through Steam until the engine is running, then through Proton.
+	Auto,
+	/// Always through Steam's `-applaunch`.
+	Steam,
+	/// Always through Proton directly.
+	Proton,
+}
+
+/// Everything a launch needs, usually from the CLI and config file.
+#[derive(Debug, Clone)]
+pub struct LaunchConfig {
+	/// Folder name of the Proton version to run the engine with.
+	pub proton_version: Option<String>,
+	/// `"64"` or `"32"`.
+	pub arch: Option<String>,
+	/// The wallpapers to open, `collection:` ones included.
+	pub wallpaper_ids: Vec<WallpaperAssignment>,
+	/// Detected from the first output when missing, 0 leaves it to the
+	/// engine.
+	pub width: Option<u32>,
+	/// Like `width`.
+	pub height: Option<u32>,
+	/// Show a preview image of each wallpaper.
+	pub preview: bool,
+	/// Detected from the terminal when missing.
+	pub previewer: Option<Previewer>,
+	/// Preview size in terminal cells, detected from the terminal when
+	/// missing.
+	pub preview_size: Option<(u32, u32)>,
+	/// Reuse preview frames extracted by earlier runs.
+	pub preview_cache: bool,
+	/// Reuse Workshop collections fetched within the last day.
+	pub collection_cache: bool,
+	/// How long to wait for each wallpaper window to appear.
+	pub window_timeout: Duration,
+	/// How often to start a wallpaper again when its window doesn't appear.
+	pub launch_retries: u32,
+	/// Start every wallpaper before waiting for their windows, which cold
+	/// prefixes don't always cope with.
+	pub parallel: bool,
+	/// How long to wait for an already running engine to exit.
+	pub stop_timeout: Duration,
+	/// Open the wallpapers in an already running engine instead of
+	/// stopping it first.
+	pub no_restart: bool,
+	/// Through Steam or Proton, see [`SpawnMode`].
+	pub spawn_mode: SpawnMode,
+	/// Start Steam if it isn't running instead of waiting for the user to.
+	pub start_steam: bool,
+	/// How long to wait for Steam after starting it.
+	pub steam_timeout: Duration,
+	/// How long to wait for the user to start Steam.
+	pub wait_for_steam_timeout: Duration,
+	/// Wallpaper Engine's frame rate limit.
+	pub fps: Option<u32>,
+	/// Shrink wallpapers that declare a resolution to their aspect ratio
+	/// instead of stretching them.
+	pub respect_aspect: bool,
+	/// 0 mutes the wallpapers.
+	pub volume: Option<u8>,
+	/// Show one wallpaper at a time, switching after this long.
+	pub rotate: Option<Duration>,
+	/// Keep running and pause the wallpapers while a fullscreen window is
+	/// focused.
+	pub pause_on_fullscreen: bool,
+	/// Keep running and pause the wallpapers while on battery power.
+	pub pause_on_battery: bool,
+	/// Keep running until interrupted, then stop the wallpapers.
+	pub keep_alive: bool,
+	/// Stop the wallpapers before exiting even when not interrupted.
+	pub stop_after: bool,
+	/// Keep running until interrupted and open the wallpapers again when
+	/// the engine exits.
+	pub restart_on_crash: bool,
+	/// Usually [`power::POWER_SUPPLY_PATH`].
+	pub power_supply_path: PathBuf,
+	/// How often to check whether to pause.
+	pub poll_interval: Duration,
+	/// How the launched wallpapers are reported.
+	pub output: OutputFormat,
+	/// Print the commands that change anything instead of running them.
+	pub dry_run: bool,
+	/// Print progress as JSON lines for frontends.
+	pub events: bool,
+	/// Print the Proton launcher and the variables set for it before
+	/// opening the wallpapers.
+	pub print_env: bool,
+	/// Proton folder to use instead of looking up `proton_version`.
+	pub proton_root: Option<PathBuf>,
+	/// Show every wallpaper and ask before changing anything.
+	pub confirm: bool,
+	/// Whether the windows go behind the others, see [`Layer`].
+	pub layer: Layer,
+	/// Needed to resolve `collection:` IDs.
+	pub steam_api_key: Option<String>,
+	/// Passed to Wallpaper Engine after the managed arguments.
+	pub engine_args: Vec<String>,
+	/// Extra environment variables for the engine, e.g. `DRI_PRIME`.
+	pub env: Vec<(String, String)>,
+	/// Where the engine's output goes instead of the terminal.
+	pub log_file: Option<PathBuf>,
+	/// Leave out `-nobrowse`, which web wallpapers need.
+	pub browser: bool,
+	/// A program and its arguments to run Proton with, e.g. `gamemoderun`.
+	pub run_prefix: Vec<String>,
+}
+
+/// A wallpaper ID, optionally sized for an output, parsed from `ID[:OUTPUT]`.
+#[derive(Debug, Clone)]
+pub struct WallpaperAssignment {
+	/// Workshop ID, or `collection:ID`.
+	pub id: String,
+	/// Output to take the resolution from.
+	pub output: Option<String>,
+}
+impl FromStr for WallpaperAssignment {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (prefix, rest) = match s.strip_prefix(COLLECTION_PREFIX) {
+			Some(rest) => (COLLECTION_PREFIX, rest),
+			None => ("", s),
+		};
+		let (id, output) = match rest.split_once(':') {
+			Some((id, output)) => (id, Some(output.to_string())),
+			None => (rest, None),
+		};
+		if id.is_empty() {
+			return Err("missing wallpaper ID".to_string());
+		}
+		if output.as_deref() == Some("") {
+			return Err("missing output name after ':'".to_string());
+		}
+		Ok(WallpaperAssignment {
+			id: format!("{}{}", prefix, id),
+			output,
+		})
+	}
+}
+
+const COLLECTION_PREFIX: &str = "collection:";
+
+/// Replaces `collection:` IDs with the wallpapers in them, which keep the
+/// collection's output.
+fn expand_collections(
+	assignments: &[WallpaperAssignment],
+	api_key: Option<&str>,
+	cache: bool,
+) -> Result<Vec<WallpaperAssignment>> {
+	let mut expanded = Vec::new();
+	for assignment in assignments {
+		match assignment.id.strip_prefix(COLLECTION_PREFIX) {
+			Some(id) => {
+				for id in collection::resolve_collection(id, api_key, cache)?
+				{
+					expanded.push(WallpaperAssignment {
+						id,
+						output: assignment.output.clone(),
+					});
+				}
+			}
+			None => expanded.push(assignment.clone()),
+		}
+	}
+	Ok(expanded)
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether [`interrupt`] was called, or SIGINT or SIGTERM received after
+/// [`install_interrupt_handler`].
+pub fn interrupted() -> bool {
+	INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Makes [`launch`] and the other long waits stop early and clean up, e.g.
+/// for a GUI's cancel button, until the next [`launch`].
+pub fn interrupt() {
+	INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Makes SIGINT/SIGTERM call [`interrupt`] so a launch can clean up,
+/// instead of leaving the wallpapers rendering. A second signal exits
+/// right away in case the cleanup hangs.
+///
+/// Only programs that own their signals should call this, [`launch`]
+/// doesn't.
+pub fn install_interrupt_handler() {
+	static INSTALLED: Once = Once::new();
+	INSTALLED.call_once(|| {
+		let result = ctrlc::set_handler(|| {
+			if INTERRUPTED.swap(true, Ordering::SeqCst) {
+				std::process::exit(130);
+			}
+		});
+		if let Err(e) = result {
+			warn!("Couldn't install the interrupt handler: {}", e);
+		}
+	});
+}
+
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+static EVENTS: AtomicBool = AtomicBool::new(false);
+
+/// Whether stdout is reserved for JSON, which sends anything else to stderr.
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Prints `line` to stdout, or stderr when stdout is JSON only.
+fn print_message(line: &str) {
+	if JSON_OUTPUT.load(Ordering::Relaxed) {
+		eprintln!("{}", line);
+	} else {
+		println!("{}", line);
+	}
+}
+
+/// Prints `{"event": name, ...fields}` as one line for frontends, if
+/// [`LaunchConfig::events`] is set.
+fn event(name: &str, fields: serde_json::Value) {
+	if !EVENTS.load(Ordering::Relaxed) {
+		return;
+	}
+	let mut event = serde_json::Map::new();
+	event.insert("event".to_string(), name.into());
+	if let serde_json::Value::Object(fields) = fields {
+		event.extend(fields);
+	}
+	println!("{}", serde_json::Value::Object(event));
+}
+
+pub(crate) fn is_dry_run() -> bool {
+	DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// Makes every command that changes Steam or starts Wallpaper Engine only
+/// print itself, not just the ones [`launch`] runs.
+pub fn set_dry_run(dry_run: bool) {
+	DRY_RUN.store(dry_run, Ordering::Relaxed);
+}
+
+/// Quotes the command like a shell would need it.
+fn format_command(command: &Command) -> String {
+	let quote = |arg: &str| {
+		if arg.is_empty()
+			|| arg.contains(|c: char| {
+				c.is_whitespace() || "'\"\\#$&;|*?()<>`!~[]{}".contains(c)
+			}) {
+			format!("'{}'", arg.replace('\'', "'\\''"))
+		} else {
+			arg.to_string()
+		}
+	};
+	// Only the variables set on the command itself, not the inherited ones.
+	let envs = command.get_envs().filter_map(|(key, value)| {
+		Some(format!(
+			"{}={}",
+			key.to_string_lossy(),
+			quote(&value?.to_string_lossy())
+		))
+	});
+	let argv = [command.get_program()]
+		.into_iter()
+		.chain(command.get_args())
+		.map(|arg| quote(&arg.to_string_lossy()));
+	envs.chain(argv).collect::<Vec<_>>().join(" ")
+}
+
+/// Prints the command during `--dry-run`, returning whether it should be
+/// skipped.
+pub(crate) fn dry_run(command: &Command) -> bool {
+	if !is_dry_run() {
+		debug!("Running {}", format_command(command));
+		return false;
+	}
+
+	print_message(&format!("[dry-run] {}", format_command(command)));
+	true
+}
+
+/// Uses the current mode of the first enabled output.
+fn detect_resolution() -> Option<(u32, u32)> {
+	output::outputs()
+		.first()
+		.map(|output| (output.width, output.height))
+}
+
+/// Checks `predicate` until it's true or `timeout` passes, backing off from
+/// 50ms to 1s between checks. Returns whether it became true.
+pub(crate) fn wait_until<F: FnMut() -> bool>(
+	mut predicate: F,
+	timeout: Duration,
+) -> bool {
+	let start = Instant::now();
+	let mut delay = Duration::from_millis(50);
+	loop {
+		if predicate() {
+			return true;
+		}
+		let remaining = timeout.saturating_sub(start.elapsed());
+		if remaining.is_zero() {
+			return false;
+		}
+		thread::sleep(delay.min(remaining));
+		delay = (delay * 2).min(Duration::from_secs(1));
+	}
+}
+
+/// Returns early without an error when interrupted.
+fn wait_for_window(title: &str, timeout: Duration) -> Result<Opened> {
+	if !wait_until(|| window_title_exists(title) || interrupted(), timeout) {
+		return Err(window_timed_out(title, timeout));
+	}
+	Ok(if window_title_exists(title) {
+		Opened::Launched
+	} else {
+		Opened::Interrupted
+	})
+}
+
+fn window_timed_out(title: &str, timeout: Duration) -> anyhow::Error {
+	anyhow!(
+		"window {:?} didn't appear within {}s, Wallpaper Engine may \
+		 have failed to start",
+		title,
+		timeout.as_secs()
+	)
+}
+
+fn show_info(dir: &Path) {
+	if let Some(info) = read_project_info(dir) {
+		if let Some(title) = info.title.clone() {
+			println!("## {}", title);
+		}
+		if let Some(kind) = &info.kind {
+			println!("Type: {}", kind);
+		}
+		if let Some(tags) = &info.tags {
+			println!("Tags: {}", tags.join(", "));
+		}
+		if let Some(rating) = &info.contentrating {
+			println!("Rating: {}", rating);
+		}
+		if let Some(desc) = info.description {
+			if info.title.is_some() {
+				println!();
+			}
+			println!("{}", desc);
+		}
+	}
+}
+
+/// Makes the window named `title` behave like the desktop background, only
+/// warning when the compositor can't.
+fn send_to_back(title: &str) {
+	if let Err(e) = window::window_backend().send_to_back(title) {
+		warn!("Couldn't send {:?} to the back: {}", title, e);
+	}
+}
+
+fn window_class_exists(class: &str) -> bool {
+	window::window_backend().class_exists(class)
+}
+
+/// Whether a window is named exactly `title`.
+fn window_title_exists(title: &str) -> bool {
+	window::window_backend().title_exists(title)
+}
+
+/// See [`CommandRunner::process_running`].
+fn process_running(names: &[&str]) -> bool {
+	command_runner().process_running(names)
+}
+
+/// Whether the focused window covers a whole output, not counting the
+/// wallpapers. Only X11 windows are visible to xdotool.
+fn fullscreen_window_focused(outputs: &[output::Output]) -> bool {
+	let Ok(xdotool) = XDOTOOL_BIN.path() else {
+		return false;
+	};
+	let runner = command_runner();
+	let Ok(name) = runner.output(
+		Command::new(xdotool).args(["getactivewindow", "getwindowname"]),
+	) else {
+		return false;
+	};
+	if !name.status.success()
+		|| String::from_utf8_lossy(&name.stdout).starts_with("Wallpaper #")
+	{
+		return false;
+	}
+
+	// WINDOW=123
+	// X=0
+	// Y=0
+	// WIDTH=2560
+	// HEIGHT=1440
+	let Ok(geometry) = runner.output(Command::new(xdotool).args([
+		"getactivewindow",
+		"getwindowgeometry",
+		"--shell",
+	])) else {
+		return false;
+	};
+	let geometry = String::from_utf8_lossy(&geometry.stdout);
+	let value = |key: &str| {
+		geometry
+			.lines()
+			.find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+			.and_then(|value| value.trim().parse::<i64>().ok())
+	};
+	let (Some(x), Some(y), Some(width), Some(height)) =
+		(value("X"), value("Y"), value("WIDTH"), value("HEIGHT"))
+	else {
+		return false;
+	};
+	outputs.iter().any(|output| {
+		(x, y, width, height)
+			== (
+				i64::from(output.x),
+				i64::from(output.y),
+				i64::from(output.width),
+				i64::from(output.height),
+			)
+	})
+}
+
+/// Whether Wallpaper Engine is running.
+pub fn we_is_running() -> bool {
+	process_running(&["wallpaper32.exe", "wallpaper64.exe"])
+}
+
+/// Checks for Steam's processes first, since on Wayland its windows may not
+/// be visible to xdotool.
+pub fn steam_is_running() -> bool {
+	process_running(&["steam", "steamwebhelper"])
+		|| window_class_exists("steamwebhelper")
+}
+
+/// How to start Wallpaper Engine.
+pub enum SteamOrProton {
+	/// Through Steam, which starts the engine if it isn't running.
+	Steam,
+	/// Through an already running engine.
+	Proton(Rc<Proton>),
+}
+
+/// A tool's `proton` launcher and the variables it needs, which are set on
+/// every command that runs it instead of on our own process.
+#[derive(Debug)]
+pub struct Proton {
+	/// The `proton` launcher.
+	pub script: PathBuf,
+	/// The Steam variables the launcher needs, with their values.
+	pub env: Vec<(&'static str, OsString)>,
+}
+impl Proton {
+	/// The launcher of `sc`, run in Wallpaper Engine's prefix.
+	pub fn new(sc: &SteamCompat) -> Result<Proton> {
+		Ok(Proton {
+			script: sc.proton_script()?,
+			env: compat_env(sc).into(),
+		})
+	}
+}
+
+/// Engine settings shared by every wallpaper in a run.
+pub struct EngineOptions {
+	/// Passed as `-fps`.
+	pub fps: Option<u32>,
+	/// Passed as `-volume`, 0 mutes.
+	pub volume: Option<u8>,
+	/// Appended verbatim after the managed arguments.
+	pub extra_args: Vec<String>,
+	/// Set on the engine's command, except for the compat variables.
+	pub env: Vec<(String, String)>,
+	/// Appended to instead of inheriting stdout and stderr.
+	pub log_file: Option<PathBuf>,
+	/// Let the engine start its browser (CEF) instead of passing
+	/// `-nobrowse`.
+	pub browser: bool,
+	/// Put in front of the `proton` command, found on PATH.
+	pub run_prefix: Vec<String>,
+}
+
+/// Variables Proton needs, which `--env` can't override.
+const COMPAT_ENV: [&str; 3] = [
+	"PROTON_DIR",
+	"STEAM_COMPAT_DATA_PATH",
+	"STEAM_COMPAT_CLIENT_INSTALL_PATH",
+];
+
+/// Parses `KEY=VALUE`.
+pub fn parse_env(s: &str) -> Result<(String, String), String> {
+	match s.split_once('=') {
+		Some((key, value)) if !key.is_empty() => {
+			Ok((key.to_string(), value.to_string()))
+		}
+		_ => Err("expected KEY=VALUE, e.g. DRI_PRIME=1".to_string()),
+	}
+}
+
+/// `path` as Wine sees it, e.g. `Z:\home\me\My Wallpapers\project.json`.
+///
+/// Proton pretends that the Z: drive on "Windows" is the root folder. Spaces
+/// need no quoting since every argument is passed to the engine as is.
+pub fn wine_path(path: &Path) -> Result<String> {
+	let path = path.to_str().ok_or_else(|| {
+		anyhow!("{:?} isn't valid UTF-8, which Wine can't open", path)
+	})?;
+	Ok(format!("Z:{}", path.replace('/', "\\")))
+}
+
+/// Opens the wallpaper at `file_path` (a Proton `Z:` path) in a window
+/// named `title`.
+pub fn start_wallpaper(
+	steam_or_proton: SteamOrProton,
+	wallpaper_engine: &Path,
+	title: &str,
+	file_path: &str,
+	(width, height): (u32, u32),
+	kind: WallpaperType,
+	options: &EngineOptions,
+) -> Result<()> {
+	match kind {
+		WallpaperType::Web if !options.browser => warn!(
+			"Web wallpapers need Wallpaper Engine's browser (CEF), pass \
+			 --browser to let it start"
+		),
+		WallpaperType::Web => warn!(
+			"Web wallpapers use Wallpaper Engine's browser (CEF), which may \
+			 need additional setup under Proton"
+		),
+		WallpaperType::Video => warn!(
+			"Video wallpapers may fail to play if Proton lacks the codec"
+		),
+		WallpaperType::Application => warn!(
+			"Application wallpapers run their own program, which may not \
+			 work under Proton"
+		),
+		WallpaperType::Scene | WallpaperType::Unknown => {}
+	}
+
+	let mut args = Vec::new();
+	if !options.browser {
+		args.push("-nobrowse".to_string());
+	}
+	args.extend([
+		"-control".to_string(),
+		"openWallpaper".to_string(),
+		"-file".to_string(),
+		file_path.to_string(),
+		"-playInWindow".to_string(),
+		title.to_string(),
+	]);
+	// 0 means the engine picks that axis itself.
+	if width != 0 {
+		args.extend(["-width".to_string(), width.to_string()]);
+	}
+	if height != 0 {
+		args.extend(["-height".to_string(), height.to_string()]);
+	}
+	if let Some(fps) = options.fps {
+		args.extend(["-fps".to_string(), fps.to_string()]);
+	}
+	args.extend(options.extra_args.iter().cloned());
+
+	let mut command = match steam_or_proton {
+		SteamOrProton::Steam => {
+			let mut command = Command::new(STEAM_BIN.path()?);
+			command
+				.process_group(0)
+				.arg("-applaunch")
+				.arg(app_id().to_string())
+				.args(&args);
+			command
+		}
+		SteamOrProton::Proton(proton) => {
+			let mut command = match options.run_prefix.split_first() {
+				Some((program, prefix_args)) => {
+					let mut command = Command::new(program);
+					command.args(prefix_args).arg(&proton.script);
+					command
+				}
+				None => Command::new(&proton.script),
+			};
+			command
+				.envs(proton.env.iter().map(|(key, value)| (key, value)))
+				.arg("run")
+				.arg(wallpaper_engine)
+				.args(&args);
+			command
+		}
+	};
+	// The compat variables are the ones `proton` was given.
+	command.envs(
+		options
+			.env
+			.iter()
+			.filter(|(key, _)| !COMPAT_ENV.contains(&key.as_str()))
+			.map(|(key, value)| (key, value)),
+	);
+	if !dry_run(&command) {
+		if let Some(path) = &options.log_file {
+			let log = OpenOptions::new()
+				.create(true)
+				.append(true)
+				.open(path)
+				.map_err(|e| {
+					anyhow!("can't open log file {:?}: {}", path, e)
+				})?;
+			command.stdout(log.try_clone()?).stderr(log);
+		}
+		command_runner().spawn(&mut command).map_err(|e| {
+			anyhow!("failed to run {:?}: {}", command.get_program(), e)
+		})?;
+	}
+
+	Ok(())
+}
+
+/// Everything needed to open wallpapers once the engine is set up.
+struct Session {
+	proton: Rc<Proton>,
+	wallpaper_engine: PathBuf,
+	options: EngineOptions,
+	window_timeout: Duration,
+	launch_retries: u32,
+	spawn_mode: SpawnMode,
+	preview: bool,
+	previewer: Option<Previewer>,
+	preview_size: Option<(u32, u32)>,
+	preview_cache: bool,
+	output: OutputFormat,
+	respect_aspect: bool,
+	layer: Layer,
+	/// Start every wallpaper before waiting for any of their windows.
+	parallel: bool,
+	/// Random per run so titles don't collide with other windows.
+	nonce: String,
+}
+
+/// What [`start_wallpaper`] needs for one wallpaper.
+struct Launch {
+	title: String,
+	file_path: String,
+	size: (u32, u32),
+	kind: WallpaperType,
+}
+
+/// How opening a wallpaper ended, when it didn't fail.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Opened {
+	Launched,
+	/// Given up on before its window appeared because of an interrupt.
+	Interrupted,
+}
+impl Session {
+	/// `Wallpaper #N (ID nonce)`, keeping the `Wallpaper #N` prefix window
+	/// rules match on.
+	fn window_title(&self, index: usize, id: &str) -> String {
+		format!("Wallpaper #{} ({} {})", index, id, self.nonce)
+	}
+
+	/// Opens the wallpaper in a window named `title` and waits for it.
+	///
+	/// `progress` is its position in the list and the list's length.
+	fn open_wallpaper(
+		&self,
+		title: &str,
+		id: &str,
+		(width, height): (u32, u32),
+		progress: Option<(usize, usize)>,
+	) -> Result<Opened> {
+		let dir = self.announce(title, id, progress)?;
+		let result = self.start_and_wait(title, &dir, (width, height));
+		self.report(title, id, &dir, &result);
+		result
+	}
+
+	/// Like [`Session::open_wallpaper`] for every `(title, id, geometry)`,
+	/// one after the other or all at once with `parallel`.
+	///
+	/// Wallpapers not started because of an interrupt are
+	/// [`Opened::Interrupted`] too, without a JSON line.
+	fn open_wallpapers(
+		&self,
+		wallpapers: &[(String, &String, (u32, u32))],
+	) -> Vec<Result<Opened>> {
+		let total = wallpapers.len();
+		if !self.parallel {
+			return wallpapers
+				.iter()
+				.enumerate()
+				.map(|(i, (title, id, geometry))| {
+					if interrupted() {
+						return Ok(Opened::Interrupted);
+					}
+					self.open_wallpaper(
+						title,
+						id,
+						*geometry,
+						Some((i + 1, total)),
+					)
+				})
+				.collect();
+		}
+
+		let mut results = Vec::new();
+		let mut opened = Vec::new();
+		let mut pending = Vec::new();
+		for (i, (title, id, geometry)) in wallpapers.iter().enumerate() {
+			if interrupted() {
+				results.push(Ok(Opened::Interrupted));
+				continue;
+			}
+			let launch = self
+				.announce(title, id, Some((i + 1, total)))
+				.and_then(|dir| {
+					let launch = self.prepare(title, &dir, *geometry)?;
+					opened.push((i, dir));
+					Ok(launch)
+				});
+			match launch {
+				// Until its window appears or it times out.
+				Ok(launch) => {
+					results.push(Ok(Opened::Interrupted));
+					pending.push((i, launch));
+				}
+				Err(e) => results.push(Err(e)),
+			}
+		}
+
+		// One shared timeout, and a retry for the ones still missing.
+		for attempt in 0..=self.launch_retries {
+			pending.retain(|(i, launch)| {
+				if interrupted() {
+					return false;
+				}
+				let through = self.steam_or_proton();
+				let via_steam = matches!(through, SteamOrProton::Steam);
+				if let Err(e) = self.spawn(launch, through) {
+					results[*i] = Err(e);
+					return false;
+				}
+				// Steam only starts the engine once, so the rest have to go
+				// to the running engine instead.
+				if via_steam && !is_dry_run() {
+					wait_until(
+						|| {
+							we_is_running()
+								|| window_title_exists(&launch.title)
+								|| interrupted()
+						},
+						self.window_timeout,
+					);
+				}
+				true
+			});
+			if is_dry_run() {
+				for (i, _) in pending.drain(..) {
+					results[i] = Ok(Opened::Launched);
+				}
+			}
+
+			let all_appeared = wait_until(
+				|| {
+					pending.retain(|(i, launch)| {
+						if !window_title_exists(&launch.title) {
+							return true;
+						}
+						results[*i] = self
+							.window_appeared(&launch.title)
+							.map(|()| Opened::Launched);
+						false
+					});
+					pending.is_empty() || interrupted()
+				},
+				self.window_timeout,
+			);
+			if all_appeared {
+				break;
+			}
+			if attempt < self.launch_retries {
+				for (_, launch) in &pending {
+					let e =
+						window_timed_out(&launch.title, self.window_timeout);
+					warn!("{}, retrying", e);
+				}
+			}
+		}
+		if !interrupted() {
+			for (i, launch) in pending {
+				results[i] =
+					Err(window_timed_out(&launch.title, self.window_timeout));
+			}
+		}
+
+		for (i, dir) in opened {
+			let (title, id, _) = &wallpapers[i];
+			self.report(title, id, &dir, &results[i]);
+		}
+		results
+	}
+
+	/// Finds the wallpaper and says it's launching it.
+	fn announce(
+		&self,
+		title: &str,
+		id: &str,
+		progress: Option<(usize, usize)>,
+	) -> Result<PathBuf> {
+		let dir = library::find_wallpaper(id)?;
+		event(
+			"launching",
+			serde_json::json!({
+				"id": id,
+				"window_title": title,
+				"index": progress.map(|(i, _)| i),
+				"total": progress.map(|(_, n)| n),
+			}),
+		);
+
+		if self.output == OutputFormat::Text {
+			match progress {
+				Some((i, n)) => println!("\n# [{}/{}] {}", i, n, title),
+				None => println!("\n# {}", title),
+			}
+			show_info(&dir);
+			// The wallpaper can still launch without its preview.
+			if self.preview
+				&& let Err(e) = show_preview(
+					&dir,
+					self.previewer,
+					self.preview_size,
+					self.preview_cache,
+				) {
+				warn!("Couldn't show the preview of {}: {}", id, e);
+			}
+		}
+		Ok(dir)
+	}
+
+	/// The JSON line of a wallpaper, for `--output json`.
+	fn report(
+		&self,
+		title: &str,
+		id: &str,
+		dir: &Path,
+		result: &Result<Opened>,
+	) {
+		if self.output == OutputFormat::Json {
+			let info = read_project_info(dir);
+			println!(
+				"{}",
+				serde_json::json!({
+					"id": id,
+					"title": info.and_then(|info| info.title),
+					"window_title": title,
+					"launched": matches!(result, Ok(Opened::Launched)),
+					"interrupted": matches!(result, Ok(Opened::Interrupted)),
+				})
+			);
+		}
+	}
+
+	fn start_and_wait(
+		&self,
+		title: &str,
+		dir: &Path,
+		geometry: (u32, u32),
+	) -> Result<Opened> {
+		let launch = self.prepare(title, dir, geometry)?;
+		// Cold prefixes often need a second try.
+		for attempt in 0..=self.launch_retries {
+			self.spawn(&launch, self.steam_or_proton())?;
+
+			if is_dry_run() {
+				break;
+			}
+			match wait_for_window(title, self.window_timeout) {
+				Ok(Opened::Launched) => {
+					return self
+						.window_appeared(title)
+						.map(|()| Opened::Launched);
+				}
+				Ok(Opened::Interrupted) => return Ok(Opened::Interrupted),
+				Err(e) if attempt < self.launch_retries => {
+					warn!("{}, retrying", e);
+				}
+				Err(e) => return Err(e),
+			}
+		}
+		self.set_volume(title).map(|()| Opened::Launched)
+	}
+
+	fn prepare(
+		&self,
+		title: &str,
+		dir: &Path,
+		(width, height): (u32, u32),
+	) -> Result<Launch> {
+		let info = read_project_info(dir);
+		// Letterbox instead of stretching, 0 still leaves it to the engine.
+		let (width, height) = match info.as_ref().and_then(ProjectInfo::size)
+		{
+			Some(size)
+				if self.respect_aspect && width != 0 && height != 0 =>
+			{
+				project::fit_aspect(size, (width, height))
+			}
+			_ => (width, height),
+		};
+		// Prefer the file the wallpaper actually runs when it's there.
+		let file = info
+			.as_ref()
+			.and_then(|info| info.file.as_ref())
+			.map(|file| dir.join(file))
+			.filter(|file| file.is_file())
+			.unwrap_or_else(|| dir.join("project.json"));
+		Ok(Launch {
+			title: title.to_string(),
+			file_path: wine_path(&file)?,
+			size: (width, height),
+			kind: info
+				.map_or(WallpaperType::Unknown, |info| info.wallpaper_type()),
+		})
+	}
+
+	fn spawn(&self, launch: &Launch, through: SteamOrProton) -> Result<()> {
+		start_wallpaper(
+			through,
+			&self.wallpaper_engine,
+			&launch.title,
+			&launch.file_path,
+			launch.size,
+			launch.kind,
+			&self.options,
+		)
+	}
+
+	fn window_appeared(&self, title: &str) -> Result<()> {
+		event(
+			"window_appeared",
+			serde_json::json!({ "window_title": title }),
+		);
+		if self.layer == Layer::Background {
+			send_to_back(title);
+		}
+		self.set_volume(title)
+	}
+
+	/// The engine only takes this once the wallpaper is open.
+	fn set_volume(&self, title: &str) -> Result<()> {
+		if let Some(volume) = self.options.volume {
+			self.send_control(&[
+				"setVolume",
+				"-volume",
+				&volume.to_string(),
+				"-playInWindow",
+				title,
+			])?;
+		}
+
+		Ok(())
+	}
+
+	fn steam_or_proton(&self) -> SteamOrProton {
+		match self.spawn_mode {
+			SpawnMode::Auto if !we_is_running() => SteamOrProton::Steam,
+			SpawnMode::Steam => SteamOrProton::Steam,
+			SpawnMode::Auto | SpawnMode::Proton => {
+				SteamOrProton::Proton(self.proton.clone())
+			}
+		}
+	}
+
+	fn stop_wallpaper(&self, title: &str) -> Result<()> {
+		stop_wallpaper(
+			&self.proton,
+			&self.wallpaper_engine,
+			title,
+			self.window_timeout,
+		)
+	}
+
+	fn send_control(&self, command: &[&str]) -> Result<()> {
+		send_control(&self.proton, &self.wallpaper_engine, command)
+	}
+}
+
+/// Closes the wallpaper in the window named `title` and waits for the
+/// window to go away.
+pub fn stop_wallpaper(
+	proton: &Proton,
+	wallpaper_engine: &Path,
+	title: &str,
+	timeout: Duration,
+) -> Result<()> {
+	send_control(
+		proton,
+		wallpaper_engine,
+		&["closeWallpaper", "-playInWindow", title],
+	)?;
+	if is_dry_run() {
+		return Ok(());
+	}
+
+	if !wait_until(|| !window_title_exists(title), timeout) {
+		return Err(anyhow!(
+			"window {:?} is still open after {}s",
+			title,
+			timeout.as_secs()
+		));
+	}
+	Ok(())
+}
+
+/// Sends `-control <command>` to the running engine.
+fn send_control(
+	proton: &Proton,
+	wallpaper_engine: &Path,
+	command: &[&str],
+) -> Result<()> {
+	let env = proton.env.iter().map(|(key, value)| (key, value));
+	let mut proton = Command::new(&proton.script);
+	proton
+		.envs(env)
+		.arg("run")
+		.arg(wallpaper_engine)
+		.args(["-nobrowse", "-control"])
+		.args(command);
+	if !dry_run(&proton) {
+		command_runner()
+			.status(&mut proton)
+			.map_err(|e| anyhow!("failed to run Proton: {}", e))?;
+	}
+	Ok(())
+}
+
+/// Prints the installed wallpapers as a table, or JSON.
+pub fn list(json: bool) -> Result<()> {
+	let wallpapers = library::installed_wallpapers()
+		.into_iter()
+		.map(|(id, dir)| {
+			let title = read_project_info(&dir).and_then(|info| info.title);
+			(id, title)
+		})
+		.collect::<Vec<_>>();
+	print_wallpapers(&wallpapers, json)
+}
+
+/// Prints the folder name of every installed Proton version and custom
+/// compatibility tool, the values `--proton-version` takes.
+pub fn list_proton() -> Result<()> {
+	let tools = compat::installed_tools();
+	if tools.is_empty() {
+		warn!(
+			"No Proton versions found in {:?} or {:?}",
+			common_dir(&STEAM_PATH),
+			compat::COMPATIBILITYTOOLS_D.as_path()
+		);
+	}
+	for tool in tools {
+		println!("{}", tool);
+	}
+	Ok(())
+}
+
+/// Prints the installed wallpapers whose title or description contains
+/// `query` and that have every tag in `tags`, ignoring case.
+pub fn search(query: &str, tags: &[String], json: bool) -> Result<()> {
+	let query = query.to_lowercase();
+	let contains = |text: &Option<String>| {
+		text.as_ref()
+			.is_some_and(|text| text.to_lowercase().contains(&query))
+	};
+	let wallpapers = library::installed_wallpapers()
+		.into_iter()
+		.filter_map(|(id, dir)| {
+			let info = read_project_info(&dir)?;
+			let has_tags = tags.iter().all(|tag| {
+				info.tags
+					.iter()
+					.flatten()
+					.any(|t| t.eq_ignore_ascii_case(tag))
+			});
+			((contains(&info.title) || contains(&info.description))
+				&& has_tags)
+				.then_some((id, info.title))
+		})
+		.collect::<Vec<_>>();
+	print_wallpapers(&wallpapers, json)
+}
+
+fn print_wallpapers(
+	wallpapers: &[(String, Option<String>)],
+	json: bool,
+) -> Result<()> {
+	if json {
+		let wallpapers = wallpapers
+			.iter()
+			.map(
+				|(id, title)| serde_json::json!({ "id": id, "title": title }),
+			)
+			.collect::<Vec<_>>();
+		println!("{}", serde_json::to_string_pretty(&wallpapers)?);
+		return Ok(());
+	}
+
+	let id_width = wallpapers
+		.iter()
+		.map(|(id, _)| id.len())
+		.chain(["ID".len()])
+		.max()
+		.unwrap_or_default();
+	println!("{:id_width$}  Title", "ID");
+	for (id, title) in wallpapers {
+		let line = format!(
+			"{:id_width$}  {}",
+			id,
+			title.as_deref().unwrap_or_default()
+		);
+		println!("{}", line.trim_end());
+	}
+
+	Ok(())
+}
+
+/// Prints the enabled outputs as a table, or JSON, ordered by position.
+pub fn monitors(json: bool) -> Result<()> {
+	let mut outputs = output::outputs();
+	if outputs.is_empty() {
+		return Err(anyhow!(
+			"no outputs found, install `wlr-randr` or `xrandr`"
+		));
+	}
+	outputs.sort_by(|a, b| (a.x, a.y, &a.name).cmp(&(b.x, b.y, &b.name)));
+
+	if json {
+		let outputs = outputs
+			.iter()
+			.map(|output| {
+				serde_json::json!({
+					"name": output.name,
+					"width": output.width,
+					"height": output.height,
+					"x": output.x,
+					"y": output.y,
+				})
+			})
+			.collect::<Vec<_>>();
+		println!("{}", serde_json::to_string_pretty(&outputs)?);
+		return Ok(());
+	}
+
+	let rows = outputs
+		.iter()
+		.map(|output| {
+			(
+				output.name.as_str(),
+				format!("{}x{}", output.width, output.height),
+				format!("{},{}", output.x, output.y),
+			)
+		})
+		.collect::<Vec<_>>();
+	let name_width = rows
+		.iter()
+		.map(|(name, _, _)| name.len())
+		.chain(["Name".len()])
+		.max()
+		.unwrap_or_default();
+	let size_width = rows
+		.iter()
+		.map(|(_, size, _)| size.len())
+		.chain(["Resolution".len()])
+		.max()
+		.unwrap_or_default();
+	println!(
+		"{:name_width$}  {:size_width$}  Position",
+		"Name", "Resolution"
+	);
+	for (name, size, position) in rows {
+		println!("{:name_width$}  {:size_width$}  {}", name, size, position);
+	}
+
+	Ok(())
+}
+
+/// Prints a wallpaper's metadata.
+pub fn info(id: &str) -> Result<()> {
+	let dir = library::find_wallpaper(id)?;
+	let info = read_project_info(&dir)
+		.ok_or(anyhow!("couldn't read {:?}", dir.join("project.json")))?;
+
+	println!("ID: {}", id);
+	if let Some(title) = info.title {
+		println!("Title: {}", title);
+	}
+	if let Some(kind) = info.kind {
+		println!("Type: {}", kind);
+	}
+	if let Some(tags) = info.tags {
+		println!("Tags: {}", tags.join(", "));
+	}
+	if let Some(rating) = info.contentrating {
+		println!("Rating: {}", rating);
+	}
+	if let Some(file) = info.file {
+		println!("File: {}", dir.join(file).display());
+	}
+	if let Some(desc) = info.description {
+		println!("\n{}", desc);
+	}
+
+	Ok(())
+}
+
+/// Errors with a hint if Proton's prefix for Wallpaper Engine doesn't exist
+/// yet, since launching into it fails silently.
+fn check_prefix() -> Result<()> {
+	let pfx = COMPATDATA_PATH.join("pfx");
+	if !pfx.is_dir() {
+		return Err(anyhow!(
+			"Proton prefix not initialized: {:?}\nRun Wallpaper Engine \
+			 once through Steam to create it",
+			pfx
+		));
+	}
+	Ok(())
+}
+
+/// Checks everything `wex` needs, printing a line per check, and errors if
+/// anything required is missing.
+pub fn doctor() -> Result<()> {
+	let mut failed = 0;
+	let mut check = |ok: bool, required: bool, message: String| {
+		let status = match (ok, required) {
+			(true, _) => "ok",
+			(false, true) => "FAIL",
+			(false, false) => "warn",
+		};
+		println!("[{}] {}", status, message);
+		if !ok && required {
+			failed += 1;
+		}
+	};
+
+	check(
+		STEAM_PATH.exists(),
+		true,
+		format!("Steam root: {}", STEAM_PATH.display()),
+	);
+
+	// Optional ones say what they're for.
+	let backend = window::window_backend().binary();
+	let binaries = [
+		(&STEAM_BIN, None),
+		(backend, None),
+		(&XDOTOOL_BIN, Some("--pause-on-fullscreen")),
+		(&CHAFA_BIN, Some("previews")),
+		(&preview::VIU_BIN, Some("previews")),
+		(&preview::KITTY_BIN, Some("previews")),
+		(&preview::WEZTERM_BIN, Some("previews")),
+		(&preview::FFMPEG_BIN, Some("video previews")),
+		(&collection::CURL_BIN, Some("collections")),
+	];
+	// xdotool is listed twice when it's the window backend.
+	let binaries = binaries.into_iter().filter(|(binary, purpose)| {
+		purpose.is_none() || !std::ptr::eq(*binary, backend)
+	});
+	for (binary, purpose) in binaries {
+		let message = match (binary.path(), purpose) {
+			(Ok(path), _) => format!("{}: {}", binary.name, path.display()),
+			(Err(_), Some(purpose)) => {
+				format!("{}: not found, needed for {}", binary.name, purpose)
+			}
+			(Err(_), None) => format!("{}: not found", binary.name),
+		};
+		check(binary.path().is_ok(), purpose.is_none(), message);
+	}
+
+	let engines = ["64", "32"].map(|arch| {
+		WALLPAPER_ENGINE_PATH.join(format!("wallpaper{}.exe", arch))
+	});
+	let found = engines
+		.iter()
+		.filter(|exe| exe.exists())
+		.collect::<Vec<_>>();
+	check(
+		!found.is_empty(),
+		true,
+		if found.is_empty() {
+			format!(
+				"Wallpaper Engine: not found in {:?}",
+				*WALLPAPER_ENGINE_PATH
+			)
+		} else {
+			format!("Wallpaper Engine: {:?}", found)
+		},
+	);
+
+	match check_prefix() {
+		Ok(()) => check(
+			true,
+			true,
+			format!("Proton prefix: {}", COMPATDATA_PATH.display()),
+		),
+		Err(e) => check(false, true, e.to_string()),
+	}
+
+	// Any query that needs the X server works, but other backends don't
+	// need xdotool, only Wine needs the display then.
+	let display = if std::ptr::eq(backend, &XDOTOOL_BIN) {
+		XDOTOOL_BIN.path().is_ok_and(|xdotool| {
+			command_runner()
+				.output(Command::new(xdotool).arg("getmouselocation"))
+				.is_ok_and(|output| output.status.success())
+		})
+	} else {
+		display_problem().is_none()
+	};
+	check(
+		display,
+		true,
+		format!(
+			"X display: {}",
+			if display {
+				env::var("DISPLAY")
+					.unwrap_or_else(|_| "reachable".to_string())
+			} else {
+				display_problem().unwrap_or_else(|| {
+					"xdotool can't reach it, is Xwayland running?".to_string()
+				})
+			}
+		),
+	);
+
+	if failed > 0 {
+		return Err(anyhow!("{} required checks failed", failed));
+	}
+	Ok(())
+}
+
+/// Why the wallpaper windows can't open, judged from the session's
+/// variables.
+fn display_problem() -> Option<String> {
+	let set = |key| env::var_os(key).is_some_and(|value| !value.is_empty());
+	if set("DISPLAY") {
+		return None;
+	}
+	let wayland = set("WAYLAND_DISPLAY")
+		|| env::var("XDG_SESSION_TYPE").is_ok_and(|kind| kind == "wayland");
+	Some(if wayland {
+		"Wayland session without Xwayland (DISPLAY isn't set), Wine can't \
+		 open the wallpaper windows. Enable Xwayland in your compositor, e.g. \
+		 with xwayland-satellite"
+			.to_string()
+	} else {
+		"No X display (DISPLAY isn't set), Wine can't open the wallpaper \
+		 windows"
+			.to_string()
+	})
+}
+
+/// Restores the compatibility tool Wallpaper Engine used before `wex`
+/// changed it.
+pub fn reset_compat() -> Result<()> {
+	match compat::reset_compat_tool(app_id())? {
+		Some(name) if name.is_empty() => {
+			println!("Restored Steam's default compatibility tool")
+		}
+		Some(name) => println!("Restored the compatibility tool to {}", name),
+		None => println!("No previous compatibility tool to restore"),
+	}
+	Ok(())
+}
+
+/// Stops every wallpaper, then Wallpaper Engine itself, or only the one in
+/// the window named `title`.
+pub fn stop(
+	config: &LaunchConfig,
+	timeout: Duration,
+	title: Option<&str>,
+) -> Result<()> {
+	let (sc, wallpaper_engine) = resolve_engine(config)?;
+	if !we_is_running() {
+		info!("Wallpaper Engine isn't running");
+		return Ok(());
+	}
+
+	let proton = Proton::new(&sc)?;
+	if let Some(title) = title {
+		if !window_title_exists(title) {
+			return Err(anyhow!("no window named {:?}", title));
+		}
+		stop_wallpaper(&proton, &wallpaper_engine, title, timeout)?;
+		info!("Closed {:?}", title);
+		return Ok(());
+	}
+
+	send_control(&proton, &wallpaper_engine, &["stop"])?;
+	// `-control stop` only stops the wallpapers, Steam closes the engine.
+	stop_engine(timeout)?;
+	info!("Wallpaper Engine stopped");
+
+	Ok(())
+}
+
+/// Closes Wallpaper Engine through Steam without touching the wallpapers
+/// first, so it needs no Proton.
+pub fn kill(timeout: Duration) -> Result<()> {
+	// `+app_stop` would start Steam if it isn't running.
+	if !we_is_running() {
+		info!("Wallpaper Engine isn't running");
+		return Ok(());
+	}
+	stop_engine(timeout)?;
+	info!("Wallpaper Engine stopped");
+	Ok(())
+}
+
+/// Starts Steam in its own process group so it outlives us, then waits for
+/// it to come up.
+fn start_steam(timeout: Duration) -> Result<()> {
+	let mut steam = Command::new(STEAM_BIN.path()?);
+	steam
+		.process_group(0)
+		.stdin(Stdio::null())
+		.stdout(Stdio::null())
+		.stderr(Stdio::null());
+	if dry_run(&steam) {
+		return Ok(());
+	}
+
+	info!("Starting Steam...");
+	command_runner()
+		.spawn(&mut steam)
+		.map_err(|e| anyhow!("failed to start Steam: {}", e))?;
+	if !wait_until(|| steam_is_running() || interrupted(), timeout) {
+		return Err(anyhow!(
+			"Steam didn't start within {}s",
+			timeout.as_secs()
+		));
+	}
+	Ok(())
+}
+
+/// Asks Steam to close the engine until it's gone, backing off between
+/// attempts.
+fn stop_engine(timeout: Duration) -> Result<()> {
+	let mut app_stop = Command::new(STEAM_BIN.path()?);
+	app_stop.arg("+app_stop").arg(app_id().to_string());
+	if dry_run(&app_stop) {
+		return Ok(());
+	}
+
+	// Steam sometimes ignores the first request, so it's repeated on every
+	// check.
+	let mut status = Ok(());
+	let mut rejected = None;
+	let stopped = wait_until(
+		|| {
+			if interrupted() {
+				return true;
+			}
+			match command_runner().status(&mut app_stop) {
+				Err(e) => {
+					status = Err(e);
+					return true;
+				}
+				Ok(exit) if !exit.success() => {
+					if rejected.is_none() {
+						warn!(
+							"`steam +app_stop` exited with {}, retrying",
+							exit
+						);
+					}
+					rejected = Some(exit);
+				}
+				Ok(_) => rejected = None,
+			}
+			!we_is_running()
+		},
+		timeout,
+	);
+	status.map_err(|e| anyhow!("failed to run `steam +app_stop`: {}", e))?;
+	if !stopped && let Some(exit) = rejected {
+		return Err(anyhow!(
+			"Steam kept rejecting `+app_stop` ({}) for {}s, make sure \
+			 it's fully started and logged in",
+			exit,
+			timeout.as_secs()
+		));
+	}
+	if !stopped {
+		return Err(anyhow!(
+			"Steam accepted `+app_stop`, but Wallpaper Engine is \
+			 still running after {}s, try `pkill -f 'wallpaper(32|64).exe'`",
+			timeout.as_secs()
+		));
+	}
+	Ok(())
+}
+
+/// Finds the Proton tool and Wallpaper Engine binary picked by the flags.
+fn resolve_engine(config: &LaunchConfig) -> Result<(SteamCompat, PathBuf)> {
+	let sc = match &config.proton_root {
+		Some(root) => SteamCompat::from_path(root)?,
+		None => {
+			let proton_version =
+				config.proton_version.clone().ok_or(anyhow!(
+					"no Proton version, pass --proton-version or set \
+				 proton_version in {:?}",
+					config::config_path().unwrap_or_default()
+				))?;
+			SteamCompat::find(&proton_version)?
+		}
+	};
+
+	debug!("{:#?}", sc);
+	debug!("Internal name: {}", sc.internal_name()?);
+
+	let engine_exe = |arch: &str| {
+		WALLPAPER_ENGINE_PATH.join(format!("wallpaper{}.exe", arch))
+	};
+	let arch = match config.arch.clone() {
+		Some(arch) => {
+			if arch != "64" && arch != "32" {
+				return Err(anyhow!("arch must be 64 or 32, got {:?}", arch));
+			}
+			arch
+		}
+		// Whichever is installed, 64-bit first.
+		None => ["64", "32"]
+			.into_iter()
+			.find(|arch| engine_exe(arch).exists())
+			.unwrap_or("64")
+			.to_string(),
+	};
+
+	let mut wallpaper_engine = engine_exe(&arch);
+	let other = engine_exe(if arch == "64" { "32" } else { "64" });
+	if !wallpaper_engine.exists() && other.exists() {
+		warn!(
+			"{:?} not found, using {:?} instead",
+			wallpaper_engine.file_name().unwrap_or_default(),
+			other.file_name().unwrap_or_default()
+		);
+		wallpaper_engine = other;
+	}
+	if !wallpaper_engine.exists() {
+		return Err(anyhow!(
+			"Wallpaper Engine not found: {:?}",
+			wallpaper_engine
+		));
+	}
+
+	Ok((sc, wallpaper_engine))
+}
+
+/// The env variables Proton needs.
+fn compat_env(sc: &SteamCompat) -> [(&'static str, OsString); 4] {
+	[
+		("PROTON_DIR", sc.path.clone().into()),
+		// Steam sets it too, so the windows match however the engine started.
+		("SteamGameId", app_id().to_string().into()),
+		("STEAM_COMPAT_DATA_PATH", COMPATDATA_PATH.clone().into()),
+		(
+			"STEAM_COMPAT_CLIENT_INSTALL_PATH",
+			STEAM_PATH.clone().into(),
+		),
+	]
+}
+
+/// What `--print-env` shows, everything Proton gets that isn't inherited.
+/// On stderr, so stdout stays JSON with `--output json`.
+fn print_env(proton: &Proton, extra: &[(String, String)]) {
+	eprintln!("# proton: {}", proton.script.display());
+	for (key, value) in &proton.env {
+		eprintln!("{}={}", key, value.to_string_lossy());
+	}
+	for (key, value) in extra {
+		if !COMPAT_ENV.contains(&key.as_str()) {
+			eprintln!("{}={}", key, value);
+		}
+	}
+}
+
+/// Sets up Proton and opens every wallpaper in `config`.
+///
+/// An [`interrupt`] from before the call is forgotten, so a cancelled
+/// launch doesn't cancel the next one too.
+pub fn launch(mut config: LaunchConfig) -> Result<()> {
+	INTERRUPTED.store(false, Ordering::SeqCst);
+	DRY_RUN.store(config.dry_run, Ordering::Relaxed);
+	EVENTS.store(config.events, Ordering::Relaxed);
+	JSON_OUTPUT.store(config.output == OutputFormat::Json, Ordering::Relaxed);
+	if let Some(problem) = display_problem() {
+		warn!("{}", problem);
+	}
+
+	let (sc, wallpaper_engine) = resolve_engine(&config)?;
+	let proton = Rc::new(Proton::new(&sc)?);
+
+	config.wallpaper_ids = expand_collections(
+		&config.wallpaper_ids,
+		config.steam_api_key.as_deref(),
+		config.collection_cache,
+	)?;
+	if config.wallpaper_ids.is_empty() {
+		return Err(anyhow!(
+			"no wallpapers provided, pass --wallpaper-ids or set \
+			 wallpaper_ids in {:?}",
+			config::config_path().unwrap_or_default()
+		));
+	}
+
+	// Fail early instead of partway through.
+	for binary in [&STEAM_BIN, window::window_backend().binary()] {
+		binary.path()?;
+	}
+	if let Some(program) = config.run_prefix.first()
+		&& command_runner().which(program).is_none()
+	{
+		return Err(anyhow!(
+			"`{}` from --run-prefix not found, install it and make \
+			 sure it's on your PATH",
+			program
+		));
+	}
+
+	// Resolve every output up front so a typo doesn't leave it half launched.
+	let mut geometries = Vec::new();
+	if config.wallpaper_ids.iter().any(|a| a.output.is_some()) {
+		let outputs = output::outputs();
+		for assignment in &config.wallpaper_ids {
+			let Some(name) = &assignment.output else {
+				geometries.push(None);
+				continue;
+			};
+			let output = outputs
+				.iter()
+				.find(|output| &output.name == name)
+				.ok_or_else(|| {
+				anyhow!(
+					"output {:?} not found, available outputs: {}",
+					name,
+					outputs
+						.iter()
+						.map(|output| output.name.as_str())
+						.collect::<Vec<_>>()
+						.join(", ")
+				)
+			})?;
+			geometries.push(Some((output.width, output.height)));
+		}
+	} else {
+		geometries.resize(config.wallpaper_ids.len(), None);
+	}
+
+	check_prefix()?;
+
+	// Check every wallpaper too, launching only some of them is worse.
+	let missing = config
+		.wallpaper_ids
+		.iter()
+		.filter(|assignment| {
+			!library::find_wallpaper(&assignment.id)
+				.is_ok_and(|dir| dir.join("project.json").exists())
+		})
+		.map(|assignment| assignment.id.as_str())
+		.collect::<Vec<_>>();
+	if !missing.is_empty() {
+		return Err(anyhow!(
+			"no project.json for wallpapers: {}\nSearched: {:?}",
+			missing.join(", "),
+			library::wallpaper_dirs()
+		));
+	}
+
+	if config.confirm && !confirm_wallpapers(&config)? {
+		print_message("Nothing launched");
+		return Ok(());
+	}
+
+	if config.layer == Layer::Background && !is_dry_run() {
+		// Proton's Wine names the windows after `SteamGameId`.
+		window::window_backend()
+			.use_as_background(&format!("steam_app_{}", app_id()))?;
+	}
+
+	if !steam_is_running() {
+		event(
+			"steam_wait",
+			serde_json::json!({ "starting": config.start_steam }),
+		);
+		if config.start_steam {
+			start_steam(config.steam_timeout)?;
+		} else if !is_dry_run() {
+			warn!("Waiting for Steam to start...");
+			warn!("You must do this manually, or pass --start-steam.");
+			// Nothing has been changed yet, so there's nothing to undo.
+			let started = wait_until(
+				|| steam_is_running() || interrupted(),
+				config.wait_for_steam_timeout,
+			);
+			if interrupted() {
+				return Ok(());
+			}
+			if !started {
+				return Err(anyhow!(
+					"Steam wasn't started within {}s",
+					config.wait_for_steam_timeout.as_secs()
+				));
+			}
+		}
+	}
+
+	// The compat tool only matters to Steam, which won't start it again.
+	let reuse = config.no_restart && we_is_running();
+	if reuse {
+		info!("Wallpaper Engine is already running, adding the wallpapers");
+		event("engine_reused", serde_json::json!({}));
+	} else {
+		event("engine_stopping", serde_json::json!({}));
+		stop_engine(config.stop_timeout)?;
+		if interrupted() {
+			return Ok(());
+		}
+
+		event("applying_compat", serde_json::json!({ "tool": sc.name }));
+		sc.apply_to_game(app_id())?;
+	}
+	for (key, _) in &config.env {
+		if COMPAT_ENV.contains(&key.as_str()) {
+			warn!("Ignoring --env {}, wex sets it for Proton", key);
+		}
+	}
+	if config.print_env {
+		print_env(&proton, &config.env);
+	}
+
+	let (width, height) = match (config.width, config.height) {
+		(Some(width), Some(height)) => (width, height),
+		(width, height) => {
+			let detected = detect_resolution().unwrap_or(DEFAULT_RESOLUTION);
+			(width.unwrap_or(detected.0), height.unwrap_or(detected.1))
+		}
+	};
+
+	let session = Session {
+		proton,
+		wallpaper_engine,
+		options: EngineOptions {
+			fps: config.fps,
+			volume: config.volume,
+			extra_args: config.engine_args.clone(),
+			env: config.env.clone(),
+			log_file: config.log_file.clone(),
+			browser: config.browser,
+			run_prefix: config.run_prefix.clone(),
+		},
+		window_timeout: config.window_timeout,
+		launch_retries: config.launch_retries,
+		spawn_mode: if reuse {
+			SpawnMode::Proton
+		} else {
+			config.spawn_mode
+		},
+		// They were just shown.
+		preview: config.preview && !config.confirm,
+		previewer: config.previewer,
+		preview_size: config.preview_size,
+		preview_cache: config.preview_cache,
+		output: config.output,
+		respect_aspect: config.respect_aspect,
+		layer: config.layer,
+		parallel: config.parallel,
+		nonce: nonce(),
+	};
+
+	let total = config.wallpaper_ids.len();
+	let mut launched = Vec::new();
+	let mut skipped = Vec::new();
+	let mut failures = Vec::new();
+	// Windows this run may have opened, interrupted ones can still appear.
+	let mut titles = Vec::new();
+	if let Some(interval) = config.rotate {
+		rotate(&session, &config.wallpaper_ids, (width, height), interval)?;
+	} else {
+		let wallpapers = config
+			.wallpaper_ids
+			.iter()
+			.zip(geometries)
+			.enumerate()
+			.map(|(i, (assignment, geometry))| {
+				(
+					session.window_title(i, &assignment.id),
+					&assignment.id,
+					geometry.unwrap_or((width, height)),
+				)
+			})
+			.collect::<Vec<_>>();
+		// One broken wallpaper shouldn't keep the rest from launching.
+		let results = session.open_wallpapers(&wallpapers);
+		for (wallpaper, result) in wallpapers.into_iter().zip(results) {
+			match result {
+				Ok(Opened::Launched) => {
+					titles.push(wallpaper.0.clone());
+					launched.push(wallpaper);
+				}
+				Ok(Opened::Interrupted) => {
+					titles.push(wallpaper.0.clone());
+					skipped.push(wallpaper.1.clone());
+				}
+				Err(e) => {
+					warn!("{}", e);
+					failures.push((wallpaper.1.clone(), e));
+				}
+			}
+		}
+
+		event(
+			"done",
+			serde_json::json!({
+				"launched": launched.iter().map(|(_, id, _)| id).collect::<Vec<_>>(),
+				"failed": failures.iter().map(|(id, _)| id).collect::<Vec<_>>(),
+				"interrupted": skipped,
+			}),
+		);
+		if config.output == OutputFormat::Text {
+			print!("\nLaunched {} of {} wallpapers", launched.len(), total);
+			if skipped.is_empty() {
+				println!();
+			} else {
+				println!(", {} interrupted", skipped.len());
+			}
+			for (id, e) in &failures {
+				println!("  {}: {}", id, e);
+			}
+		}
+	}
+
+	if (config.pause_on_fullscreen || config.pause_on_battery)
+		&& !interrupted()
+	{
+		let outputs = output::outputs();
+		pause_while(&session, config.poll_interval, || {
+			(config.pause_on_battery
+				&& power::on_battery(&config.power_supply_path))
+				|| (config.pause_on_fullscreen
+					&& fullscreen_window_focused(&outputs))
+		})?;
+	} else if config.restart_on_crash && !interrupted() {
+		info!("Watching Wallpaper Engine until interrupted");
+		supervise(&session, &launched)?;
+	} else if config.keep_alive && !interrupted() {
+		info!("Keeping the wallpapers running until interrupted");
+		wait_until(interrupted, Duration::MAX);
+	}
+
+	// The engine outlives us, so the wallpapers stay up unless asked not to.
+	let stop = interrupted() || config.stop_after;
+	if interrupted() {
+		info!("Interrupted, stopping the wallpapers");
+	}
+	if stop && reuse {
+		// The ones that were open before this run aren't ours to stop.
+		for title in &titles {
+			if let Err(e) = session.stop_wallpaper(title) {
+				warn!("{}", e);
+			}
+		}
+	} else if stop {
+		session.send_control(&["stop"])?;
+	}
+
+	if !failures.is_empty() {
+		return Err(anyhow!(
+			"{} of {} wallpapers failed to launch",
+			failures.len(),
+			total
+		));
+	}
+	Ok(())
+}
+
+/// Pauses the wallpapers whenever `should_pause` says so and plays them
+/// again afterwards, until interrupted.
+fn pause_while(
+	session: &Session,
+	interval: Duration,
+	should_pause: impl Fn() -> bool,
+) -> Result<()> {
+	let mut paused = false;
+	while !interrupted() {
+		let pause = should_pause();
+		if pause != paused {
+			info!(
+				"{} the wallpapers",
+				if pause { "Pausing" } else { "Playing" }
+			);
+			session.send_control(&[if pause { "pause" } else { "play" }])?;
+			paused = pause;
+		}
+		thread::sleep(interval);
+	}
+	Ok(())
+}
+
+/// Shows the info and preview of every wallpaper in `config`, then asks
+/// whether to launch them.
+fn confirm_wallpapers(config: &LaunchConfig) -> Result<bool> {
+	let total = config.wallpaper_ids.len();
+	let json = config.output == OutputFormat::Json;
+	for (i, assignment) in config.wallpaper_ids.iter().enumerate() {
+		let dir = library::find_wallpaper(&assignment.id)?;
+		// Only the titles when stdout is JSON, the rest would end up there.
+		if json {
+			let title = read_project_info(&dir).and_then(|info| info.title);
+			eprintln!(
+				"[{}/{}] {}  {}",
+				i + 1,
+				total,
+				assignment.id,
+				title.unwrap_or_default()
+			);
+			continue;
+		}
+		println!("\n# [{}/{}] {}", i + 1, total, assignment.id);
+		show_info(&dir);
+		if config.preview
+			&& let Err(e) = show_preview(
+				&dir,
+				config.previewer,
+				config.preview_size,
+				config.preview_cache,
+			) {
+			warn!("Couldn't show the preview of {}: {}", assignment.id, e);
+		}
+	}
+
+	let prompt = format!("\nLaunch {} wallpapers? [y/N] ", total);
+	if json {
+		eprint!("{}", prompt);
+		io::stderr().flush()?;
+	} else {
+		print!("{}", prompt);
+		io::stdout().flush()?;
+	}
+	let mut answer = String::new();
+	io::stdin().read_line(&mut answer)?;
+	Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// How many times [`supervise`] restarts the engine within
+/// [`RESTART_WINDOW`] before giving up.
+const MAX_RESTARTS: usize = 3;
+const RESTART_WINDOW: Duration = Duration::from_secs(300);
+
+/// Opens `wallpapers` again whenever the engine exits, until interrupted.
+fn supervise(
+	session: &Session,
+	wallpapers: &[(String, &String, (u32, u32))],
+) -> Result<()> {
+	let mut restarts = VecDeque::new();
+	loop {
+		wait_until(|| interrupted() || !we_is_running(), Duration::MAX);
+		if interrupted() {
+			return Ok(());
+		}
+
+		let now = Instant::now();
+		restarts.retain(|&time| now.duration_since(time) < RESTART_WINDOW);
+		if restarts.len() == MAX_RESTARTS {
+			return Err(anyhow!(
+				"Wallpaper Engine exited {} times within {}s, giving up",
+				MAX_RESTARTS + 1,
+				RESTART_WINDOW.as_secs()
+			));
+		}
+		restarts.push_back(now);
+
+		warn!("Wallpaper Engine exited, opening the wallpapers again");
+		for result in session.open_wallpapers(wallpapers) {
+			if let Err(e) = result {
+				warn!("{}", e);
+			}
+		}
+	}
+}
+
+/// A short random hex string.
+fn nonce() -> String {
+	// std seeds every `RandomState` randomly, which is plenty for this.
+	let hash = RandomState::new().hash_one(std::process::id());
+	format!("{:06x}", hash & 0xff_ffff)
+}
+
+/// Shows one wallpaper at a time, moving to the next after `interval`,
+/// until interrupted.
+///
+/// Outputs are looked up again for every wallpaper, so ones that were
+/// unplugged are skipped until they're back.
+fn rotate(
+	session: &Session,
+	assignments: &[WallpaperAssignment],
+	default_geometry: (u32, u32),
+	interval: Duration,
+) -> Result<()> {
+	let mut skipped = 0;
+	for assignment in assignments.iter().cycle() {
+		let geometry = match &assignment.output {
+			None => default_geometry,
+			Some(name) => {
+				match output::outputs().iter().find(|o| &o.name == name) {
+					Some(output) => (output.width, output.height),
+					None => {
+						warn!(
+							"Output {:?} is gone, skipping wallpaper {}",
+							name, assignment.id
+						);
+						// Don't spin while every output is unplugged.
+						skipped += 1;
+						if skipped == assignments.len() {
+							skipped = 0;
+							if wait_until(interrupted, interval) {
+								return Ok(());
+							}
+						}
+						continue;
+					}
+				}
+			}
+		};
+		skipped = 0;
+
+		// Every wallpaper reuses the first slot so window rules still apply.
+		let title = &session.window_title(0, &assignment.id);
+		// Move on to the next one as usual, it might work.
+		if let Err(e) =
+			session.open_wallpaper(title, &assignment.id, geometry, None)
+		{
+			warn!("{}", e);
+		}
+
+		if wait_until(interrupted, interval) {
+			return session.stop_wallpaper(title);
+		}
+		// A window that won't close shouldn't end the rotation either.
+		if let Err(e) = session.stop_wallpaper(title) {
+			warn!("{}", e);
+		}
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+	use super::*;
+	use crate::{runner::tests::RecordingRunner, window::WindowBackendKind};
+
+	fn options() -> EngineOptions {
+		EngineOptions {
+			fps: Some(30),
+			volume: None,
+			extra_args: vec!["-extra".to_string()],
+			env: Vec::new(),
+			log_file: None,
+			browser: false,
+			run_prefix: Vec::new(),
+		}
+	}
+
+	fn proton() -> Proton {
+		Proton {
+			script: PathBuf::from("/proton/proton"),
+			env: vec![("PROTON_DIR", "/proton".into())],
+		}
+	}
+
+	#[test]
+	fn wine_paths() {
+		assert_eq!(
+			wine_path(Path::new("/home/me/My Wallpapers/1/project.json"))
+				.unwrap(),
+			r"Z:\home\me\My Wallpapers\1\project.json"
+		);
+		assert_eq!(
+			wine_path(Path::new("/mnt/Données/壁纸 (1)/scene.pkg")).unwrap(),
+			r"Z:\mnt\Données\壁纸 (1)\scene.pkg"
+		);
+		let invalid = OsStr::from_bytes(b"/wallpapers/\xff/project.json");
+		assert!(wine_path(Path::new(invalid)).is_err());
+	}
+
+	#[test]
+	fn start_wallpaper_through_steam() {
+		let runner = RecordingRunner::install();
+		start_wallpaper(
+			SteamOrProton::Steam,
+			Path::new("/engine/wallpaper64.exe"),
+			"Wallpaper #0 (1 abc)",
+			r"Z:\wallpapers\1\scene.pkg",
+			(2560, 0),
+			WallpaperType::Scene,
+			&options(),
+		)
+		.unwrap();
+		assert_eq!(
+			runner.commands(),
+			[vec![
+				"steam",
+				"-applaunch",
+				"431960",
+				"-nobrowse",
+				"-control",
+				"openWallpaper",
+				"-file",
+				r"Z:\wallpapers\1\scene.pkg",
+				"-playInWindow",
+				"Wallpaper #0 (1 abc)",
+				"-width",
+				"2560",
+				"-fps",
+				"30",
+				"-extra",
+			]]
+		);
+	}
+
+	#[test]
+	fn start_wallpaper_through_proton() {
+		let runner = RecordingRunner::install();
+		start_wallpaper(
+			SteamOrProton::Proton(Rc::new(proton())),
+			Path::new("/engine/wallpaper64.exe"),
+			"Wallpaper #1 (2 abc)",
+			r"Z:\wallpapers\2\project.json",
+			(1920, 1080),
+			WallpaperType::Scene,
+			&options(),
+		)
+		.unwrap();
+		assert_eq!(
+			runner.commands(),
+			[vec![
+				"/proton/proton",
+				"run",
+				"/engine/wallpaper64.exe",
+				"-nobrowse",
+				"-control",
+				"openWallpaper",
+				"-file",
+				r"Z:\wallpapers\2\project.json",
+				"-playInWindow",
+				"Wallpaper #1 (2 abc)",
+				"-width",
+				"1920",
+				"-height",
+				"1080",
+				"-fps",
+				"30",
+				"-extra",
+			]]
+		);
+	}
+
+	#[test]
+	fn run_prefix_wraps_proton() {
+		let runner = RecordingRunner::install();
+		let options = EngineOptions {
+			run_prefix: vec!["gamemoderun".to_string(), "-v".to_string()],
+			..options()
+		};
+		start_wallpaper(
+			SteamOrProton::Proton(Rc::new(proton())),
+			Path::new("/engine/wallpaper64.exe"),
+			"Wallpaper #0 (1 abc)",
+			r"Z:\wallpapers\1\project.json",
+			(0, 0),
+			WallpaperType::Scene,
+			&options,
+		)
+		.unwrap();
+		assert_eq!(
+			runner.commands()[0][..4],
+			["gamemoderun", "-v", "/proton/proton", "run"]
+		);
+	}
+
+	#[test]
+	fn stop_wallpaper_closes_its_window() {
+		let runner = RecordingRunner::install();
+		// No other test looks at windows.
+		let _ = window::set_window_backend(WindowBackendKind::Xdotool);
+		stop_wallpaper(
+			&proton(),
+			Path::new("/engine/wallpaper64.exe"),
+			"Wallpaper #1 (2 abc)",
+			Duration::from_secs(1),
+		)
+		.unwrap();
+		assert_eq!(
+			runner.commands(),
+			[[
+				"/proton/proton",
+				"run",
+				"/engine/wallpaper64.exe",
+				"-nobrowse",
+				"-control",
+				"closeWallpaper",
+				"-playInWindow",
+				"Wallpaper #1 (2 abc)",
+			]]
+		);
+		// The window is gone as soon as it's checked.
+		assert_eq!(
+			runner.queries(),
+			[["xdotool", "search", "--name", r"^Wallpaper #1 \(2 abc\)$"]]
+		);
+	}
+
+	#[test]
+	fn stop_commands() {
+		let runner = RecordingRunner::install();
+		let engine = Path::new("/engine/wallpaper64.exe");
+		send_control(&proton(), engine, &["stop"]).unwrap();
+		// The runner says nothing is running, so one request is enough.
+		stop_engine(Duration::from_secs(1)).unwrap();
+		assert_eq!(
+			runner.commands(),
+			[
+				vec![
+					"/proton/proton",
+					"run",
+					"/engine/wallpaper64.exe",
+					"-nobrowse",
+					"-control",
+					"stop",
+				],
+				vec!["steam", "+app_stop", "431960"],
+			]
+		);
+	}
+
+	#[test]
+	fn commands_quoted_for_the_shell() {
+		let mut command = Command::new("/proton/proton");
+		command
+			.env("PROTON_DIR", "/steam/Proton 10.0")
+			.arg("run")
+			.arg(r"Z:\home\me\1\project.json")
+			.args(["-playInWindow", "Wallpaper #0 (1 abc)", "it's", ""]);
+		assert_eq!(
+			format_command(&command),
+			concat!(
+				"PROTON_DIR='/steam/Proton 10.0' /proton/proton run ",
+				r"'Z:\home\me\1\project.json' -playInWindow ",
+				r"'Wallpaper #0 (1 abc)' 'it'\''s' ''",
+			)
+		);
+	}
+}
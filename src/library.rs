@@ -0,0 +1,249 @@
+//! Finding installed wallpapers and Wallpaper Engine in Steam's libraries.
+
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow};
+use log::warn;
+
+use crate::{
+	STEAM_PATH, common_dir, project::read_project_info, steamapps_dir,
+	vdf::Vdf, workshop_content_dir,
+};
+
+/// Every Steam library root from `libraryfolders.vdf`, starting with the one
+/// Steam itself is installed in.
+pub fn steam_library_dirs() -> Vec<PathBuf> {
+	steam_library_dirs_in(&STEAM_PATH)
+}
+
+/// [`steam_library_dirs`] of the Steam installed at `root`.
+pub fn steam_library_dirs_in(root: &Path) -> Vec<PathBuf> {
+	let mut dirs = vec![root.to_path_buf()];
+
+	let vdf_path = steamapps_dir(root).join("libraryfolders.vdf");
+	match Vdf::from_file(&vdf_path) {
+		Ok(vdf) => {
+			let folders = vdf.get("libraryfolders").map(Vdf::entries);
+			for (_, folder) in folders.unwrap_or_default() {
+				if let Some(path) = folder.get("path").and_then(Vdf::as_str) {
+					dirs.push(PathBuf::from(path));
+				}
+			}
+		}
+		Err(e) if vdf_path.exists() => {
+			warn!("Couldn't read Steam libraries: {}", e);
+		}
+		Err(_) => {}
+	}
+
+	// `~/.steam/steam` is usually a symlink to one of the listed libraries.
+	let mut seen = Vec::new();
+	dirs.retain(|dir| {
+		let canonical = fs::canonicalize(dir).unwrap_or_else(|_| dir.clone());
+		if seen.contains(&canonical) {
+			false
+		} else {
+			seen.push(canonical);
+			true
+		}
+	});
+	dirs
+}
+
+/// The Wallpaper Engine workshop folder of every library.
+pub fn workshop_content_dirs() -> Vec<PathBuf> {
+	workshop_content_dirs_in(&STEAM_PATH)
+}
+
+/// [`workshop_content_dirs`] for the Steam installed at `root`.
+pub fn workshop_content_dirs_in(root: &Path) -> Vec<PathBuf> {
+	steam_library_dirs_in(root)
+		.iter()
+		.map(|dir| workshop_content_dir(dir))
+		.collect()
+}
+
+/// Where Wallpaper Engine keeps wallpapers imported or made in its editor,
+/// named after their folder instead of a Workshop ID.
+pub fn local_projects_dir_in(root: &Path) -> Option<PathBuf> {
+	find_wallpaper_engine_in(root).map(|dir| dir.join("projects/myprojects"))
+}
+
+/// Every folder wallpapers are installed in, the Workshop ones first.
+pub fn wallpaper_dirs() -> Vec<PathBuf> {
+	wallpaper_dirs_in(&STEAM_PATH)
+}
+
+/// [`wallpaper_dirs`] for the Steam installed at `root`.
+pub fn wallpaper_dirs_in(root: &Path) -> Vec<PathBuf> {
+	let mut dirs = workshop_content_dirs_in(root);
+	dirs.extend(local_projects_dir_in(root));
+	dirs
+}
+
+/// Every installed workshop or local wallpaper as `(id, dir)`, sorted by ID.
+pub fn installed_wallpapers() -> Vec<(String, PathBuf)> {
+	installed_wallpapers_in(&STEAM_PATH)
+}
+
+/// [`installed_wallpapers`] for the Steam installed at `root`.
+pub fn installed_wallpapers_in(root: &Path) -> Vec<(String, PathBuf)> {
+	let mut wallpapers = Vec::new();
+	for content_dir in wallpaper_dirs_in(root) {
+		let Ok(entries) = fs::read_dir(&content_dir) else {
+			continue;
+		};
+		for entry in entries.flatten() {
+			let dir = entry.path();
+			if !dir.is_dir() {
+				continue;
+			}
+			// Workshop IDs are numbers, this isn't one of them.
+			match entry.file_name().into_string() {
+				Ok(id) => wallpapers.push((id, dir)),
+				Err(_) => {
+					warn!("Skipping {:?}, its name isn't valid UTF-8", dir)
+				}
+			}
+		}
+	}
+	wallpapers.sort_by(|(a, _), (b, _)| {
+		a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+	});
+	wallpapers
+}
+
+/// The `n` most recently added wallpapers by folder mtime, newest first,
+/// with ties in ID order.
+pub fn latest_wallpapers(n: usize) -> Vec<(String, PathBuf)> {
+	let mut wallpapers = installed_wallpapers()
+		.into_iter()
+		.map(|(id, dir)| {
+			let modified = fs::metadata(&dir).and_then(|m| m.modified()).ok();
+			(modified, id, dir)
+		})
+		.collect::<Vec<_>>();
+	// Stable, so equal times keep the ID order from `installed_wallpapers`.
+	wallpapers.sort_by(|(a, _, _), (b, _, _)| b.cmp(a));
+	wallpapers
+		.into_iter()
+		.take(n)
+		.map(|(_, id, dir)| (id, dir))
+		.collect()
+}
+
+/// The ID of the installed wallpaper titled `title`, or else the only one
+/// whose title contains it, ignoring case.
+pub fn find_wallpaper_by_title(title: &str) -> Result<String> {
+	let wallpapers = installed_wallpapers()
+		.into_iter()
+		.filter_map(|(id, dir)| Some((id, read_project_info(&dir)?.title?)))
+		.collect::<Vec<_>>();
+
+	let mut matches = wallpapers
+		.iter()
+		.filter(|(_, t)| t == title)
+		.collect::<Vec<_>>();
+	if matches.is_empty() {
+		let lowercase = title.to_lowercase();
+		matches = wallpapers
+			.iter()
+			.filter(|(_, t)| t.to_lowercase().contains(&lowercase))
+			.collect();
+	}
+	match matches.as_slice() {
+		[] => Err(anyhow!("no installed wallpaper titled {:?}", title)),
+		[(id, _)] => Ok(id.clone()),
+		_ => Err(anyhow!(
+			"{} wallpapers match {:?}, pass one of their IDs \
+			 instead:\n{}",
+			matches.len(),
+			title,
+			matches
+				.iter()
+				.map(|(id, t)| format!("  {}  {}", id, t))
+				.collect::<Vec<_>>()
+				.join("\n")
+		)),
+	}
+}
+
+/// Finds the folder of a workshop or local wallpaper in any library.
+pub fn find_wallpaper(id: &str) -> Result<PathBuf> {
+	find_wallpaper_in(&STEAM_PATH, id)
+}
+
+/// [`find_wallpaper`] for the Steam installed at `root`.
+pub fn find_wallpaper_in(root: &Path, id: &str) -> Result<PathBuf> {
+	let content_dirs = wallpaper_dirs_in(root);
+	content_dirs
+		.iter()
+		.map(|dir| dir.join(id))
+		.find(|dir| dir.is_dir())
+		.ok_or_else(|| {
+			anyhow!(
+				"wallpaper {} not found, searched: {:?}",
+				id,
+				content_dirs
+			)
+		})
+}
+
+/// Finds Wallpaper Engine's install folder in any library.
+pub fn find_wallpaper_engine() -> Option<PathBuf> {
+	find_wallpaper_engine_in(&STEAM_PATH)
+}
+
+/// [`find_wallpaper_engine`] for the Steam installed at `root`.
+pub fn find_wallpaper_engine_in(root: &Path) -> Option<PathBuf> {
+	steam_library_dirs_in(root)
+		.iter()
+		.map(|dir| common_dir(dir).join("wallpaper_engine"))
+		.find(|dir| dir.is_dir())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::env;
+
+	use super::*;
+
+	#[test]
+	fn library_folders() {
+		let root = env::temp_dir()
+			.join(format!("wex_test_library_folders_{}", std::process::id()));
+		fs::create_dir_all(steamapps_dir(&root)).unwrap();
+		fs::write(
+			steamapps_dir(&root).join("libraryfolders.vdf"),
+			format!(
+				r#""libraryfolders"
+				{{
+					// Steam's own library is listed as well.
+					"0"
+					{{
+						"path" "{}"
+						"apps" {{ "228980" "1" }}
+					}}
+					"1"
+					{{
+						"path" "/mnt/games/SteamLibrary"
+						"label" "\"Games\""
+					}}
+				}}"#,
+				root.display()
+			),
+		)
+		.unwrap();
+
+		assert_eq!(
+			steam_library_dirs_in(&root),
+			[root.clone(), PathBuf::from("/mnt/games/SteamLibrary")]
+		);
+		let _ = fs::remove_dir_all(&root);
+		// Without the file there's only the root.
+		assert_eq!(steam_library_dirs_in(&root), [root]);
+	}
+}
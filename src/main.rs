@@ -1,324 +1,514 @@
 use std::{
-	env, fs, os::unix::process::CommandExt, path::PathBuf, process::Command,
-	rc::Rc, sync::LazyLock, thread, time::Duration,
+	ffi::OsStr,
+	io::{self, IsTerminal},
+	path::PathBuf,
+	str::FromStr,
+	time::Duration,
 };
 
 use anyhow::{Result, anyhow};
-use clap::Parser;
-use compat::SteamCompat;
-use serde::Deserialize;
-use which::which;
-
-pub mod compat;
-
-pub const WALLPAPER_ENGINE_ID: u32 = 431960;
-
-pub static STEAM_PATH: LazyLock<PathBuf> =
-	LazyLock::new(|| dirs::home_dir().unwrap().join(".steam/steam"));
-pub static STEAMAPPS: LazyLock<PathBuf> =
-	LazyLock::new(|| STEAM_PATH.join("steamapps"));
-pub static COMMON: LazyLock<PathBuf> =
-	LazyLock::new(|| STEAMAPPS.join("common"));
-pub static COMPATDATA_PATH: LazyLock<PathBuf> =
-	LazyLock::new(|| STEAMAPPS.join("compatdata").join(431960.to_string()));
-pub static WORKSHOP_CONTENT_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
-	STEAMAPPS.join("workshop/content").join(431960.to_string())
-});
-pub static WALLPAPER_ENGINE_PATH: LazyLock<PathBuf> =
-	LazyLock::new(|| COMMON.join("wallpaper_engine"));
-
-static STEAM_BIN: LazyLock<PathBuf> =
-	LazyLock::new(|| which("steam").unwrap());
-static PGREP_BIN: LazyLock<PathBuf> =
-	LazyLock::new(|| which("pgrep").unwrap());
-static MAGICK_BIN: LazyLock<PathBuf> =
-	LazyLock::new(|| which("magick").unwrap());
-static CHAFA_BIN: LazyLock<PathBuf> =
-	LazyLock::new(|| which("chafa").unwrap());
-static XDOTOOL_BIN: LazyLock<PathBuf> =
-	LazyLock::new(|| which("xdotool").unwrap());
+use clap::{
+	ArgAction, CommandFactory, Parser, Subcommand,
+	builder::{PossibleValue, TypedValueParser},
+	error::ErrorKind,
+};
+use clap_complete::Shell;
+use log::LevelFilter;
+use wex::{
+	LaunchConfig, OutputFormat, STEAM_PATH, SpawnMode, SteamCompat,
+	WallpaperAssignment, compat,
+	config::{self, Config},
+	doctor, expand_path, info, install_interrupt_handler, kill, launch,
+	library, list, list_proton, monitors, parse_env, power,
+	preview::{self, Previewer},
+	reset_compat, search, set_app_id, set_compat_data_path, set_dry_run,
+	set_steam_bin, steam_path_candidates, stop,
+	window::{Layer, WindowBackendKind, set_window_backend},
+};
 
 #[derive(Parser)]
 struct Args {
+	#[command(subcommand)]
+	command: Option<Commands>,
+	/// Log more, repeat for even more (-vv)
+	#[arg(short, long, action = ArgAction::Count, global = true)]
+	verbose: u8,
+	/// Only log errors
+	#[arg(short, long, global = true, conflicts_with = "verbose")]
+	quiet: bool,
 	/// Proton version folder name (e.g., "Proton 10.0" or "GE-Proton7-55") at
 	/// ~/.steam/steam/compatibilitytools.d/ or
-	/// ~/.steam/steam/steamapps/common/
-	#[arg(short, long)]
-	proton_version: String,
-	/// Architecture: 64 or 32
-	#[arg(short, long)]
-	arch: String,
+	/// ~/.steam/steam/steamapps/common/, or Steam's internal name for it
+	/// (e.g., "proton_10")
+	#[arg(
+		short,
+		long,
+		global = true,
+		value_parser = ProtonVersionParser,
+		hide_possible_values = true
+	)]
+	proton_version: Option<String>,
+	/// Steam app ID of Wallpaper Engine, only useful for testing
+	#[arg(long, global = true, env = "WEX_APP_ID", hide = true)]
+	app_id: Option<u32>,
+	/// How to find windows, auto uses Hyprland's or sway's IPC when running
+	/// under them and xdotool otherwise
+	#[arg(long, global = true, value_enum, default_value_t = WindowBackendKind::Auto)]
+	window_backend: WindowBackendKind,
+	/// Proton prefix to use instead of
+	/// ~/.steam/steam/steamapps/compatdata/431960/
+	#[arg(long, global = true, env = "WEX_COMPAT_DATA_PATH")]
+	compat_data_path: Option<PathBuf>,
+	/// Steam executable to run instead of the `steam` on PATH
+	#[arg(long, global = true)]
+	steam_bin: Option<PathBuf>,
+	/// Proton folder to use, taking precedence over --proton-version
+	#[arg(long, global = true)]
+	proton_root: Option<PathBuf>,
+	/// Architecture: 64 or 32 [default: whichever is installed, preferring
+	/// 64]
+	#[arg(short, long, global = true)]
+	arch: Option<String>,
 	/// Wallpaper IDs from ~/.steam/steam/steamapps/workshop/content/431960/
+	/// optionally followed by the output to size it for (e.g., "123456:DP-1").
+	/// "collection:ID" adds every wallpaper in a Workshop collection.
 	#[arg(short, long)]
-	wallpaper_ids: Vec<String>,
+	wallpaper_ids: Vec<WallpaperAssignment>,
+	/// Title of an installed wallpaper to launch, or a unique part of one
+	#[arg(long, value_name = "TITLE")]
+	wallpaper_titles: Vec<String>,
+	/// Also launch the N most recently added wallpapers
+	#[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+	latest: Option<u32>,
+	/// Width passed to Wallpaper Engine (0 leaves it to the engine)
+	/// [default: detected, or 1920]
+	#[arg(long)]
+	width: Option<u32>,
+	/// Height passed to Wallpaper Engine (0 leaves it to the engine)
+	/// [default: detected, or 1080]
+	#[arg(long)]
+	height: Option<u32>,
+	/// Program used to show previews [default: detected from the terminal]
+	#[arg(long)]
+	previewer: Option<Previewer>,
+	/// Don't show previews, which is the default when stdout isn't a
+	/// terminal
+	#[arg(long)]
+	no_preview: bool,
+	/// Preview size in terminal cells as WIDTHxHEIGHT [default: half the
+	/// terminal, or 40x20]
+	#[arg(long, value_name = "WxH", value_parser = preview::parse_size)]
+	preview_size: Option<(u32, u32)>,
+	/// Extract preview frames and fetch Workshop collections again instead
+	/// of using the cached ones
+	#[arg(long)]
+	no_cache: bool,
+	/// Seconds to wait for each wallpaper window to appear
+	#[arg(long, default_value_t = 30)]
+	window_timeout: u64,
+	/// How often to start a wallpaper again when its window doesn't appear
+	/// in time
+	#[arg(long, default_value_t = 1)]
+	launch_retries: u32,
+	/// Start every wallpaper at once and wait for all their windows
+	/// together instead of one after the other
+	#[arg(long, conflicts_with = "rotate")]
+	parallel: bool,
+	/// Seconds to wait for an already running Wallpaper Engine to exit
+	#[arg(long, default_value_t = 10)]
+	stop_timeout: u64,
+	/// Add the wallpapers to an already running Wallpaper Engine through
+	/// Proton instead of restarting it
+	#[arg(long)]
+	no_restart: bool,
+	/// How to hand wallpapers to Wallpaper Engine, auto uses Steam until the
+	/// engine is running and Proton after
+	#[arg(long, value_enum, default_value_t = SpawnMode::Auto)]
+	spawn_mode: SpawnMode,
+	/// Start Steam if it isn't running instead of waiting for it
+	#[arg(long)]
+	start_steam: bool,
+	/// Seconds to wait for Steam after --start-steam
+	#[arg(long, default_value_t = 60)]
+	steam_timeout: u64,
+	/// Seconds to wait for Steam to be started without --start-steam
+	#[arg(long, default_value_t = 120)]
+	wait_for_steam_timeout: u64,
+	/// Frame rate cap passed to Wallpaper Engine [default: engine setting]
+	#[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+	fps: Option<u32>,
+	/// Keep the aspect ratio of wallpapers that declare a resolution,
+	/// letterboxing them instead of stretching
+	#[arg(long)]
+	respect_aspect: bool,
+	/// Mute every listed wallpaper
+	#[arg(long, conflicts_with = "volume")]
+	mute: bool,
+	/// Volume from 0 to 100 for every listed wallpaper
+	#[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+	volume: Option<u8>,
+	/// Show the wallpapers one at a time, switching every N seconds until
+	/// interrupted
+	#[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+	rotate: Option<u64>,
+	/// Keep running and pause the wallpapers while a fullscreen window is
+	/// focused
+	#[arg(long, conflicts_with = "rotate")]
+	pause_on_fullscreen: bool,
+	/// Keep running and pause the wallpapers while on battery power
+	#[arg(long, conflicts_with = "rotate")]
+	pause_on_battery: bool,
+	/// Keep running after opening the wallpapers and only stop them once
+	/// interrupted, e.g. under a service manager
+	#[arg(long, conflicts_with = "rotate")]
+	keep_alive: bool,
+	/// Where the wallpaper windows go, background needs sway or Hyprland
+	/// with the hyprwinwrap plugin
+	#[arg(long, value_enum, default_value_t = Layer::Window)]
+	layer: Layer,
+	/// Stop the wallpapers again before exiting
+	#[arg(long)]
+	stop_after: bool,
+	/// Keep running and open the wallpapers again whenever Wallpaper Engine
+	/// exits, giving up after 4 exits within 5 minutes
+	#[arg(
+		long,
+		conflicts_with_all = ["rotate", "pause_on_fullscreen", "pause_on_battery"]
+	)]
+	restart_on_crash: bool,
+	/// Where to look for batteries for --pause-on-battery
+	#[arg(long, value_name = "PATH", default_value = power::POWER_SUPPLY_PATH)]
+	power_supply_path: PathBuf,
+	/// Milliseconds between checks for --pause-on-fullscreen and
+	/// --pause-on-battery
+	#[arg(long, value_name = "MS", default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..))]
+	poll_interval: u64,
+	/// Run Wallpaper Engine on the secondary GPU (DRI_PRIME=1)
+	#[arg(long)]
+	gpu_prime: bool,
+	/// KEY=VALUE set for Wallpaper Engine, can be repeated. Applied after
+	/// --gpu-prime, and can't override the variables Proton needs
+	#[arg(long, value_name = "KEY=VALUE", value_parser = parse_env)]
+	env: Vec<(String, String)>,
+	/// Let Wallpaper Engine start its browser, which web wallpapers need
+	/// (passes no -nobrowse)
+	#[arg(long)]
+	browser: bool,
+	/// Program (and arguments) to run Proton with when it's started
+	/// directly, e.g. "gamemoderun" or "mangohud"
+	#[arg(long, value_name = "COMMAND")]
+	run_prefix: Option<String>,
+	/// Append Wallpaper Engine's and Proton's output to this file instead of
+	/// the terminal
+	#[arg(long, value_name = "PATH")]
+	log_file: Option<PathBuf>,
+	/// Output format of the launched wallpapers, JSON prints one object per
+	/// line and no previews
+	#[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+	output: OutputFormat,
+	/// Print the commands that change Steam or start Wallpaper Engine
+	/// instead of running them
+	#[arg(long, global = true)]
+	dry_run: bool,
+	/// Show every wallpaper first and ask before launching them
+	#[arg(long)]
+	confirm: bool,
+	/// Also print progress as JSON lines for frontends, e.g.
+	/// {"event":"window_appeared","window_title":"..."}
+	#[arg(long)]
+	events: bool,
+	/// Print the Proton launcher and the environment variables set for it
+	/// before opening the wallpapers, for bug reports
+	#[arg(long)]
+	print_env: bool,
+	/// Steam Web API key used when resolving collections
+	#[arg(long, env = "STEAM_API_KEY", hide_env_values = true)]
+	steam_api_key: Option<String>,
+	/// Extra arguments appended verbatim to Wallpaper Engine's command line
+	/// when opening each wallpaper
+	#[arg(last = true, value_name = "ENGINE_ARGS")]
+	engine_args: Vec<String>,
 }
 
-#[derive(Deserialize)]
-struct ProjectInfo {
-	title: Option<String>,
-	description: Option<String>,
+#[derive(Subcommand)]
+enum Commands {
+	/// Launch the wallpapers of a profile from the config
+	Apply {
+		/// Name of a `[profiles.NAME]` table
+		profile: String,
+	},
+	/// List installed wallpapers
+	List {
+		/// Print JSON instead of a table
+		#[arg(long)]
+		json: bool,
+	},
+	/// List installed wallpapers whose title or description contains a text
+	Search {
+		/// Text to look for, ignoring case
+		query: String,
+		/// Only show wallpapers with this tag, can be repeated
+		#[arg(long)]
+		tag: Vec<String>,
+		/// Print JSON instead of a table
+		#[arg(long)]
+		json: bool,
+	},
+	/// List the enabled outputs to use in "ID:OUTPUT" assignments
+	Monitors {
+		/// Print JSON instead of a table
+		#[arg(long)]
+		json: bool,
+	},
+	/// Print a wallpaper's metadata without launching it
+	Info {
+		/// Wallpaper ID
+		id: String,
+	},
+	/// Stop running wallpapers and wait for Wallpaper Engine to exit
+	Stop {
+		/// Seconds to wait for Wallpaper Engine to exit
+		#[arg(long, default_value_t = 10)]
+		timeout: u64,
+		/// Only close the wallpaper in this window, e.g. "Wallpaper #0 (123
+		/// 1a2b3c)"
+		#[arg(long)]
+		title: Option<String>,
+	},
+	/// Only close Wallpaper Engine through Steam, e.g. on logout
+	Kill {
+		/// Seconds to wait for Wallpaper Engine to exit
+		#[arg(long, default_value_t = 10)]
+		timeout: u64,
+	},
+	/// Restore the compatibility tool Wallpaper Engine used before wex
+	/// changed it
+	ResetCompat,
+	/// Check the Steam root, external programs, Wallpaper Engine, Proton
+	/// prefix and X display, failing if anything required is missing
+	Doctor,
+	/// List the installed Proton versions --proton-version takes
+	ListProton,
+	/// Print completions for a shell, e.g. `wex completions fish`
+	Completions { shell: Shell },
 }
 
-fn wait_for_window(title: &str) {
-	while !window_title_exists(&title) {
-		thread::sleep(Duration::from_millis(100));
-	}
-}
-
-/// Renders the first frame of the GIF or the JPG.
-fn show_preview(dir: &PathBuf) -> Result<()> {
-	let gif = dir.join("preview.gif");
-	let jpg = dir.join("preview.jpg");
-
-	if jpg.exists() {
-		Command::new(CHAFA_BIN.as_path())
-			.args([
-				"--symbols=block",
-				"--fill=block",
-				"--size=40x20",
-				jpg.to_str().unwrap(),
-			])
-			.status()?;
-	} else if gif.exists() {
-		const TMP: &'static str = "/tmp/chafa_preview.png";
-
-		Command::new(MAGICK_BIN.as_path())
-			.args([format!("{}[0]", gif.to_str().unwrap()), TMP.to_string()])
-			.status()
-			.expect("failed to extract first frame of gif");
-		Command::new(CHAFA_BIN.as_path())
-			.args(["--symbols=block", "--fill=block", "--size=40x20", TMP])
-			.status()?;
-		let _ = fs::remove_file(TMP);
-	} else {
-		println!("No preview image found in {:?}", dir);
-	}
-
-	Ok(())
-}
-
-fn show_info(dir: &PathBuf) {
-	let json_path = dir.join("project.json");
-	if let Ok(content) = fs::read_to_string(json_path) {
-		if let Ok(info) = serde_json::from_str::<ProjectInfo>(&content) {
-			if let Some(title) = info.title.clone() {
-				println!("## {}", title);
-			}
-			if let Some(desc) = info.description {
-				if info.title.is_some() {
-					println!();
-				}
-				println!("{}", desc);
-			}
+/// Checks `--proton-version` against the installed tools, which shells
+/// also complete it with.
+#[derive(Clone)]
+struct ProtonVersionParser;
+impl TypedValueParser for ProtonVersionParser {
+	type Value = String;
+
+	fn parse_ref(
+		&self,
+		cmd: &clap::Command,
+		_: Option<&clap::Arg>,
+		value: &OsStr,
+	) -> Result<String, clap::Error> {
+		let name = value.to_str().ok_or_else(|| {
+			clap::Error::new(ErrorKind::InvalidUtf8).with_cmd(cmd)
+		})?;
+		// Without Steam there's nothing to check, main reports that instead.
+		if STEAM_PATH.exists()
+			&& let Err(e) = SteamCompat::find(name)
+		{
+			return Err(clap::Error::raw(
+				ErrorKind::InvalidValue,
+				format!("invalid --proton-version: {}\n", e),
+			)
+			.with_cmd(cmd));
 		}
+		Ok(name.to_string())
 	}
-}
-
-fn window_class_exists(class: &str) -> bool {
-	let status = Command::new(XDOTOOL_BIN.as_path())
-		.args(["search", "--class", class])
-		.status();
-	if let Ok(s) = status {
-		if s.success() {
-			return true;
-		}
-	}
-	false
-}
 
-fn window_title_exists(title: &str) -> bool {
-	let status = Command::new(XDOTOOL_BIN.as_path())
-		.args(["search", "--name", title])
-		.status();
-	if let Ok(s) = status {
-		if s.success() {
-			return true;
-		}
+	fn possible_values(
+		&self,
+	) -> Option<Box<dyn Iterator<Item = PossibleValue> + '_>> {
+		Some(Box::new(
+			compat::installed_tools()
+				.into_iter()
+				.map(PossibleValue::new),
+		))
 	}
-	false
 }
 
-fn we_is_running() -> bool {
-	let output = Command::new(PGREP_BIN.as_path())
-		.arg("-f")
-		.arg("wallpaper32.exe")
-		.output()
-		.expect("failed to execute pgrep");
-
-	if !output.stdout.is_empty() {
-		true
-	} else {
-		let output = Command::new(PGREP_BIN.as_path())
-			.arg("-f")
-			.arg("wallpaper64.exe")
-			.output()
-			.expect("failed to execute pgrep");
-
-		if !output.stdout.is_empty() {
-			true
+impl Args {
+	/// Fills in everything that wasn't passed on the command line.
+	fn into_launch_config(self, config: Config) -> Result<LaunchConfig> {
+		let mut wallpaper_ids: Vec<_> = if self.wallpaper_ids.is_empty()
+			&& self.wallpaper_titles.is_empty()
+			&& self.latest.is_none()
+		{
+			config
+				.wallpaper_ids
+				.iter()
+				.map(|id| WallpaperAssignment::from_str(id))
+				.collect::<Result<_, _>>()
+				.map_err(|e| {
+					anyhow!(
+						"invalid wallpaper ID in {:?}: {}",
+						config::config_path().unwrap_or_default(),
+						e
+					)
+				})?
 		} else {
-			false
+			self.wallpaper_ids
+		};
+		for title in &self.wallpaper_titles {
+			let id = library::find_wallpaper_by_title(title)?;
+			wallpaper_ids.push(WallpaperAssignment { id, output: None });
 		}
-	}
-}
-
-fn steam_is_running() -> bool {
-	window_class_exists("steamwebhelper")
-}
-
-enum SteamOrProton {
-	Steam,
-	Proton(Rc<PathBuf>),
-}
-
-fn start_wallpaper(
-	steam_or_proton: SteamOrProton,
-	wallpaper_engine: &PathBuf,
-	title: &String,
-	file_path: &String,
-) -> Result<()> {
-	let args = [
-		"-nobrowse",
-		"-control",
-		"openWallpaper",
-		"-file",
-		file_path,
-		"-playInWindow",
-		title,
-		"-width",
-		"1920",
-		"-height",
-		"1080",
-	];
-
-	match steam_or_proton {
-		SteamOrProton::Steam => {
-			Command::new(STEAM_BIN.as_path())
-				.process_group(0)
-				.arg("-applaunch")
-				.arg("431960")
-				.args(args)
-				.spawn()
-				.expect("failed to run proton Wallpaper Engine");
-		}
-		SteamOrProton::Proton(proton) => {
-			Command::new(proton.as_path())
-				.arg("run")
-				.arg(&wallpaper_engine)
-				.args(args)
-				.spawn()
-				.expect("failed to run proton Wallpaper Engine");
+		if let Some(n) = self.latest {
+			wallpaper_ids.extend(
+				library::latest_wallpapers(n as usize)
+					.into_iter()
+					.map(|(id, _)| WallpaperAssignment { id, output: None }),
+			);
 		}
+		Ok(LaunchConfig {
+			proton_version: self.proton_version.or(config.proton_version),
+			arch: self.arch.or(config.arch),
+			wallpaper_ids,
+			width: self.width.or(config.width),
+			height: self.height.or(config.height),
+			preview: !self.no_preview && io::stdout().is_terminal(),
+			previewer: self.previewer,
+			preview_size: self.preview_size,
+			preview_cache: !self.no_cache,
+			collection_cache: !self.no_cache,
+			window_timeout: Duration::from_secs(self.window_timeout),
+			launch_retries: self.launch_retries,
+			parallel: self.parallel,
+			stop_timeout: Duration::from_secs(self.stop_timeout),
+			no_restart: self.no_restart,
+			spawn_mode: self.spawn_mode,
+			start_steam: self.start_steam,
+			steam_timeout: Duration::from_secs(self.steam_timeout),
+			wait_for_steam_timeout: Duration::from_secs(
+				self.wait_for_steam_timeout,
+			),
+			fps: self.fps.or(config.fps),
+			respect_aspect: self.respect_aspect,
+			volume: if self.mute {
+				Some(0)
+			} else {
+				self.volume.or(config.volume)
+			},
+			rotate: self.rotate.map(Duration::from_secs),
+			pause_on_fullscreen: self.pause_on_fullscreen,
+			pause_on_battery: self.pause_on_battery,
+			keep_alive: self.keep_alive,
+			stop_after: self.stop_after,
+			restart_on_crash: self.restart_on_crash,
+			power_supply_path: expand_path(&self.power_supply_path),
+			poll_interval: Duration::from_millis(self.poll_interval),
+			output: self.output,
+			dry_run: self.dry_run,
+			events: self.events,
+			print_env: self.print_env,
+			proton_root: self.proton_root.as_deref().map(expand_path),
+			layer: self.layer,
+			confirm: self.confirm,
+			steam_api_key: self.steam_api_key.or(config.steam_api_key),
+			engine_args: self.engine_args,
+			env: self
+				.gpu_prime
+				.then(|| ("DRI_PRIME".to_string(), "1".to_string()))
+				.into_iter()
+				.chain(self.env)
+				.collect(),
+			log_file: self.log_file.as_deref().map(expand_path),
+			browser: self.browser,
+			run_prefix: self
+				.run_prefix
+				.iter()
+				.flat_map(|prefix| prefix.split_whitespace())
+				.map(str::to_string)
+				.collect(),
+		})
 	}
-
-	Ok(())
 }
 
 fn main() -> Result<()> {
-	let args = Args::parse();
-
-	let sc = SteamCompat::from_name(&args.proton_version).ok_or(anyhow!(
-		"Error: Proton folder not found: {:?}",
-		args.proton_version
-	))?;
-
-	println!("{:#?}", sc);
-	println!("{}", sc.internal_name());
-
-	if args.arch != "64" && args.arch != "32" {
-		eprintln!("Error: arch must be 64 or 32");
-		std::process::exit(1);
-	}
-	let proton = Rc::new(sc.path.join("proton"));
-
-	if args.wallpaper_ids.len() == 0 {
-		eprintln!("Error: no wallpapers provided");
+	let mut args = Args::parse();
+
+	let level = match (args.quiet, args.verbose) {
+		(true, _) => LevelFilter::Error,
+		(false, 0) => LevelFilter::Warn,
+		(false, 1) => LevelFilter::Info,
+		(false, 2) => LevelFilter::Debug,
+		(false, _) => LevelFilter::Trace,
+	};
+	// RUST_LOG still wins for debugging.
+	env_logger::Builder::new()
+		.filter_level(level)
+		.format_timestamp(None)
+		.format_target(false)
+		.parse_default_env()
+		.init();
+
+	if let Some(Commands::Completions { shell }) = args.command {
+		let mut command = Args::command();
+		let name = command.get_name().to_string();
+		clap_complete::generate(shell, &mut command, name, &mut io::stdout());
 		return Ok(());
 	}
 
-	if !steam_is_running() {
-		println!("Waiting for Steam to start...");
-		println!("You must do this manually.");
+	if let Some(id) = args.app_id {
+		set_app_id(id)?;
 	}
-	while !steam_is_running() {
-		thread::sleep(Duration::from_millis(100));
+	set_window_backend(args.window_backend)?;
+	if let Some(path) = args.compat_data_path.take() {
+		set_compat_data_path(expand_path(&path))?;
 	}
-
-	// let mut _steam_command = None;
-	// if !steam_is_running() {
-	// 	_steam_command =
-	// 		Some(Command::new(STEAM_BIN.as_path()).process_group(0).spawn()?);
-	// 	// eprintln!("Error: Steam isn't running");
-	// 	// return Ok(());
-	// }
-
-	let wallpaper_engine =
-		WALLPAPER_ENGINE_PATH.join(format!("wallpaper{}.exe", args.arch));
-	if !wallpaper_engine.exists() {
-		eprintln!("Wallpaper Engine not found: {:?}", wallpaper_engine);
-		std::process::exit(1);
+	if let Some(path) = args.steam_bin.take() {
+		set_steam_bin(expand_path(&path))?;
 	}
-
-	Command::new(STEAM_BIN.as_path())
-		.arg("+app_stop")
-		.arg(WALLPAPER_ENGINE_ID.to_string())
-		.status()?;
-
-	while we_is_running() {
-		Command::new(STEAM_BIN.as_path())
-			.arg("+app_stop")
-			.arg(WALLPAPER_ENGINE_ID.to_string())
-			.status()?;
-		thread::sleep(Duration::from_millis(100));
+	set_dry_run(args.dry_run);
+
+	// Doctor reports a missing Steam itself, monitors doesn't need it.
+	if !STEAM_PATH.exists()
+		&& !matches!(
+			args.command,
+			Some(Commands::Doctor | Commands::Monitors { .. })
+		) {
+		return Err(anyhow!(
+			"Steam not found, searched: {:?}\nSet STEAM_ROOT to \
+			 your Steam folder if it's somewhere else",
+			steam_path_candidates()
+		));
 	}
 
-	sc.apply_to_game(WALLPAPER_ENGINE_ID)?;
-
-	// Set the env variables Proton needs.
-	unsafe {
-		env::set_var("PROTON_DIR", &sc.path.as_path());
-		env::set_var(
-			"STEAM_COMPAT_DATA_PATH",
-			COMPATDATA_PATH.to_string_lossy().to_string(),
-		);
-		env::set_var(
-			"STEAM_COMPAT_CLIENT_INSTALL_PATH",
-			STEAM_PATH.to_string_lossy().to_string(),
-		);
+	// Only launching cleans up after itself, the rest can just die.
+	if matches!(args.command, None | Some(Commands::Apply { .. })) {
+		install_interrupt_handler();
 	}
 
-	for (i, wallpaper_id) in args.wallpaper_ids.iter().enumerate() {
-		let title = format!("Wallpaper #{}", i);
-		let dir = WORKSHOP_CONTENT_PATH.join(wallpaper_id);
-		// Proton pretends that the Z: drive on "Windows" is the root folder.
-		let file_path =
-			format!("Z:{}", dir.join("project.json").to_str().unwrap());
-
-		println!("\n# {}", title);
-		show_info(&dir);
-		show_preview(&dir)?;
-
-		start_wallpaper(
-			if !we_is_running() {
-				SteamOrProton::Steam
-			} else {
-				SteamOrProton::Proton(proton.clone())
-			},
-			&wallpaper_engine,
-			&title,
-			&file_path,
-		)?;
-
-		wait_for_window(&title);
+	match args.command.take() {
+		Some(Commands::Apply { profile }) => {
+			launch(args.into_launch_config(
+				Config::load()?.apply_profile(&profile)?,
+			)?)
+		}
+		Some(Commands::List { json }) => list(json),
+		Some(Commands::Search { query, tag, json }) => {
+			search(&query, &tag, json)
+		}
+		Some(Commands::Monitors { json }) => monitors(json),
+		Some(Commands::Info { id }) => info(&id),
+		Some(Commands::Stop { timeout, title }) => stop(
+			&args.into_launch_config(Config::load()?)?,
+			Duration::from_secs(timeout),
+			title.as_deref(),
+		),
+		Some(Commands::Kill { timeout }) => {
+			kill(Duration::from_secs(timeout))
+		}
+		Some(Commands::ResetCompat) => reset_compat(),
+		Some(Commands::Doctor) => doctor(),
+		Some(Commands::ListProton) => list_proton(),
+		Some(Commands::Completions { .. }) => unreachable!(),
+		None => launch(args.into_launch_config(Config::load()?)?),
 	}
-
-	// Stop it from rendering stuff in the background.
-	Command::new(proton.as_path())
-		.arg("run")
-		.arg(&wallpaper_engine)
-		.args(["-nobrowse", "-control", "stop"])
-		.spawn()
-		.expect("failed to run proton Wallpaper Engine");
-
-	Ok(())
 }
@@ -0,0 +1,133 @@
+//! The enabled outputs and their geometry.
+
+use std::process::Command;
+
+use crate::command_runner;
+
+/// A monitor's current mode and position.
+#[derive(Debug, Clone)]
+pub struct Output {
+	/// The connector, e.g. `DP-1`.
+	pub name: String,
+	/// In pixels.
+	pub width: u32,
+	/// In pixels.
+	pub height: u32,
+	/// Left edge in the combined layout.
+	pub x: i32,
+	/// Top edge in the combined layout.
+	pub y: i32,
+}
+
+/// Lists the enabled outputs, trying `wlr-randr` first and `xrandr` second.
+pub fn outputs() -> Vec<Output> {
+	wlr_randr_outputs()
+		.filter(|outputs| !outputs.is_empty())
+		.or_else(xrandr_outputs)
+		.unwrap_or_default()
+}
+
+fn parse_resolution(s: &str) -> Option<(u32, u32)> {
+	let (w, h) = s.split_once('x')?;
+	Some((w.trim().parse().ok()?, h.trim().parse().ok()?))
+}
+
+fn run(program: &str) -> Option<String> {
+	let runner = command_runner();
+	let output = runner
+		.output(&mut Command::new(runner.which(program)?))
+		.ok()?;
+	if !output.status.success() {
+		return None;
+	}
+	Some(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn wlr_randr_outputs() -> Option<Vec<Output>> {
+	let stdout = run("wlr-randr")?;
+
+	// Outputs start unindented, their properties are indented.
+	// DP-1 "Some Monitor"
+	//   Enabled: yes
+	//   Modes:
+	//     2560x1440 px, 143.998001 Hz (preferred, current)
+	//   Position: 0,0
+	let mut outputs = Vec::new();
+	let mut current: Option<Output> = None;
+	let mut enabled = false;
+	let mut push = |output: Option<Output>, enabled: bool| {
+		if let Some(output) = output
+			&& enabled
+		{
+			outputs.push(output);
+		}
+	};
+	for line in stdout.lines() {
+		let trimmed = line.trim();
+		if trimmed.is_empty() {
+			continue;
+		}
+		if !line.starts_with(char::is_whitespace) {
+			push(current.take(), enabled);
+			enabled = false;
+			current = Some(Output {
+				name: trimmed
+					.split_whitespace()
+					.next()
+					.unwrap_or_default()
+					.to_string(),
+				width: 0,
+				height: 0,
+				x: 0,
+				y: 0,
+			});
+			continue;
+		}
+		let Some(output) = current.as_mut() else {
+			continue;
+		};
+		if let Some(value) = trimmed.strip_prefix("Enabled:") {
+			enabled = value.trim() == "yes";
+		} else if let Some(value) = trimmed.strip_prefix("Position:") {
+			if let Some((x, y)) = value.trim().split_once(',') {
+				output.x = x.parse().unwrap_or(0);
+				output.y = y.parse().unwrap_or(0);
+			}
+		} else if trimmed.contains("current")
+			&& let Some((width, height)) =
+				trimmed.split(" px").next().and_then(parse_resolution)
+		{
+			output.width = width;
+			output.height = height;
+		}
+	}
+	push(current.take(), enabled);
+
+	Some(outputs)
+}
+
+fn xrandr_outputs() -> Option<Vec<Output>> {
+	let stdout = run("xrandr")?;
+
+	// DP-1 connected primary 2560x1440+0+0 (normal left ...) ...
+	let outputs = stdout
+		.lines()
+		.filter(|line| line.contains(" connected"))
+		.filter_map(|line| {
+			let mut words = line.split_whitespace();
+			let name = words.next()?.to_string();
+			let geometry = words.find(|word| word.contains('+'))?;
+			let mut parts = geometry.split('+');
+			let (width, height) = parse_resolution(parts.next()?)?;
+			Some(Output {
+				name,
+				width,
+				height,
+				x: parts.next()?.parse().ok()?,
+				y: parts.next()?.parse().ok()?,
+			})
+		})
+		.collect();
+
+	Some(outputs)
+}
@@ -0,0 +1,21 @@
+//! Whether the machine runs on battery.
+
+use std::{fs, path::Path};
+
+/// The usual place the kernel lists batteries and chargers.
+pub const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+
+/// Whether any battery under `power_supply` is discharging, meaning the
+/// charger is unplugged.
+pub fn on_battery(power_supply: &Path) -> bool {
+	let Ok(entries) = fs::read_dir(power_supply) else {
+		return false;
+	};
+	entries.flatten().any(|entry| {
+		let read = |file| {
+			fs::read_to_string(entry.path().join(file)).unwrap_or_default()
+		};
+		read("type").trim() == "Battery"
+			&& read("status").trim() == "Discharging"
+	})
+}
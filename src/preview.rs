@@ -0,0 +1,389 @@
+//! Wallpaper previews drawn in the terminal.
+
+use std::{
+	env, fs,
+	path::{Path, PathBuf},
+	process::{self, Command, ExitStatus, Stdio},
+	sync::atomic::{AtomicU32, Ordering},
+	time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use image::ImageFormat;
+use log::{info, warn};
+use terminal_size::{Height, Width};
+
+use crate::{
+	Binary, CHAFA_BIN, command_runner, config, project::read_project_info,
+};
+
+pub(crate) static VIU_BIN: Binary = Binary::new("viu");
+pub(crate) static KITTY_BIN: Binary = Binary::new("kitty");
+pub(crate) static WEZTERM_BIN: Binary = Binary::new("wezterm");
+pub(crate) static FFMPEG_BIN: Binary = Binary::new("ffmpeg");
+
+const PREVIEW_SIZE: (u32, u32) = (40, 20);
+/// How long a previewer or ffmpeg may take before it's killed.
+const TOOL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Parses `WxH` in terminal cells.
+pub fn parse_size(s: &str) -> Result<(u32, u32), String> {
+	let (width, height) = s
+		.split_once('x')
+		.ok_or_else(|| "expected WIDTHxHEIGHT, e.g. 40x20".to_string())?;
+	let parse = |n: &str| match n.parse() {
+		Ok(0) | Err(_) => Err(format!("invalid size {:?}", n)),
+		Ok(n) => Ok(n),
+	};
+	Ok((parse(width)?, parse(height)?))
+}
+
+/// Half the terminal in each direction, or 40x20 when it's unknown.
+pub fn detect_size() -> (u32, u32) {
+	match terminal_size::terminal_size() {
+		Some((Width(columns), Height(rows))) => {
+			(u32::from(columns / 2).max(1), u32::from(rows / 2).max(1))
+		}
+		None => PREVIEW_SIZE,
+	}
+}
+
+/// How many colors the terminal can show, for chafa.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Colors {
+	/// `NO_COLOR` is set, so only the shapes of characters are used.
+	None,
+	Ansi16,
+	Ansi256,
+	Full,
+}
+impl Colors {
+	/// Detected from `NO_COLOR`, `COLORTERM` and `TERM`, or `None` for a
+	/// terminal that can't draw a preview at all.
+	fn detect() -> Option<Colors> {
+		let var = |key| env::var(key).ok().filter(|value| !value.is_empty());
+		let term = var("TERM")?;
+		if term == "dumb" {
+			return None;
+		}
+		if var("NO_COLOR").is_some() {
+			return Some(Colors::None);
+		}
+		if var("COLORTERM").is_some_and(|c| c == "truecolor" || c == "24bit")
+		{
+			return Some(Colors::Full);
+		}
+		if term.contains("256color") {
+			return Some(Colors::Ansi256);
+		}
+		Some(Colors::Ansi16)
+	}
+
+	fn chafa_args(self) -> &'static [&'static str] {
+		match self {
+			// Blocks are all the same without color, ASCII at least has shapes.
+			Colors::None => &["--colors=none", "--symbols=ascii"],
+			Colors::Ansi16 => {
+				&["--colors=16", "--symbols=block", "--fill=block"]
+			}
+			Colors::Ansi256 => {
+				&["--colors=256", "--symbols=block", "--fill=block"]
+			}
+			Colors::Full => {
+				&["--colors=full", "--symbols=block", "--fill=block"]
+			}
+		}
+	}
+}
+
+/// Programs that can draw an image in the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Previewer {
+	/// `chafa`
+	Chafa,
+	/// `viu`
+	Viu,
+	/// `kitty +kitten icat`
+	Kitty,
+	/// `wezterm imgcat`
+	Wezterm,
+}
+impl Previewer {
+	fn binary(&self) -> &'static Binary {
+		match self {
+			Previewer::Chafa => &CHAFA_BIN,
+			Previewer::Viu => &VIU_BIN,
+			Previewer::Kitty => &KITTY_BIN,
+			Previewer::Wezterm => &WEZTERM_BIN,
+		}
+	}
+
+	/// Prefers the terminal's own image protocol, then whatever is installed.
+	pub fn detect() -> Option<Previewer> {
+		let mut candidates = Vec::new();
+		if env::var_os("KITTY_WINDOW_ID").is_some() {
+			candidates.push(Previewer::Kitty);
+		}
+		if env::var("TERM_PROGRAM").is_ok_and(|term| term == "WezTerm") {
+			candidates.push(Previewer::Wezterm);
+		}
+		candidates.extend([Previewer::Chafa, Previewer::Viu]);
+		candidates
+			.into_iter()
+			.find(|previewer| previewer.binary().path().is_ok())
+	}
+
+	fn command(
+		&self,
+		image: &Path,
+		(width, height): (u32, u32),
+		colors: Colors,
+	) -> Result<Command> {
+		let mut command = Command::new(self.binary().path()?);
+		match self {
+			Previewer::Chafa => command
+				.args(colors.chafa_args())
+				.arg(format!("--size={}x{}", width, height)),
+			Previewer::Viu => command.args([
+				"-w".to_string(),
+				width.to_string(),
+				"-h".to_string(),
+				height.to_string(),
+			]),
+			// icat only sizes images when placing them at a fixed spot.
+			Previewer::Kitty => command.args(["+kitten", "icat"]),
+			Previewer::Wezterm => command.args([
+				"imgcat".to_string(),
+				"--width".to_string(),
+				width.to_string(),
+				"--height".to_string(),
+				height.to_string(),
+			]),
+		};
+		command.arg(image);
+		Ok(command)
+	}
+}
+
+/// Runs `command`, killing it if it takes longer than [`TOOL_TIMEOUT`].
+fn run(command: &mut Command) -> Result<ExitStatus> {
+	command_runner()
+		.status_timeout(command, TOOL_TIMEOUT)?
+		.ok_or_else(|| {
+			anyhow!(
+				"{:?} timed out after {}s",
+				command.get_program(),
+				TOOL_TIMEOUT.as_secs()
+			)
+		})
+}
+
+/// Shows `image`, only noting it when the previewer times out.
+fn show_image(
+	previewer: Previewer,
+	image: &Path,
+	size: (u32, u32),
+	colors: Colors,
+) -> Result<()> {
+	if let Err(e) = run(&mut previewer.command(image, size, colors)?) {
+		warn!("No preview: {}", e);
+	}
+	Ok(())
+}
+
+/// Decodes the first frame of a GIF, animated or not, into a PNG.
+fn first_frame(gif: &Path, png: &Path) -> Result<()> {
+	let frame = image::open(gif)?;
+	frame.save_with_format(png, ImageFormat::Png)?;
+	Ok(())
+}
+
+/// Extracts the first frame of a video into a PNG with `ffmpeg`.
+fn video_frame(video: &Path, png: &Path) -> Result<()> {
+	let status = run(Command::new(FFMPEG_BIN.path()?)
+		.args(["-v", "error", "-y", "-i"])
+		.arg(video)
+		.args(["-frames:v", "1"])
+		.arg(png)
+		.stdin(Stdio::null()))?;
+	if !status.success() {
+		return Err(anyhow!("ffmpeg exited with {}", status));
+	}
+	Ok(())
+}
+
+/// A temporary PNG path that no other preview uses.
+fn frame_path() -> PathBuf {
+	static COUNTER: AtomicU32 = AtomicU32::new(0);
+
+	env::temp_dir().join(format!(
+		"wex_preview_{}_{}.png",
+		process::id(),
+		COUNTER.fetch_add(1, Ordering::Relaxed)
+	))
+}
+
+/// Where the extracted frame of a wallpaper is kept between runs.
+fn cache_path(dir: &Path) -> Option<PathBuf> {
+	let id = dir.file_name()?.to_str()?;
+	let previews = config::cache_dir()
+		.inspect_err(|e| warn!("Not caching previews: {}", e))
+		.ok()?
+		.join("previews");
+	if let Err(e) = fs::create_dir_all(&previews) {
+		warn!("Couldn't create {:?}: {}", previews, e);
+		return None;
+	}
+	// Appended, a dot in the folder name isn't an extension to replace.
+	Some(previews.join(format!("{}.png", id)))
+}
+
+/// Whether `cache` was written after `source` last changed.
+fn is_fresh(cache: &Path, source: &Path) -> bool {
+	let modified = |path| fs::metadata(path).and_then(|m| m.modified());
+	match (modified(cache), modified(source)) {
+		(Ok(cache), Ok(source)) => cache >= source,
+		_ => false,
+	}
+}
+
+/// Shows a frame extracted from `source`, reusing the cached one if it's
+/// still fresh.
+fn show_frame(
+	previewer: Previewer,
+	size: (u32, u32),
+	colors: Colors,
+	source: &Path,
+	cache: Option<PathBuf>,
+	extract: fn(&Path, &Path) -> Result<()>,
+) -> Result<()> {
+	let fresh = cache
+		.as_deref()
+		.is_some_and(|cache| is_fresh(cache, source));
+	let frame = cache.clone().unwrap_or_else(frame_path);
+	if !fresh && let Err(e) = extract(source, &frame) {
+		warn!("Couldn't decode {:?}: {}", source, e);
+		let _ = fs::remove_file(frame);
+		return Ok(());
+	}
+
+	show_image(previewer, &frame, size, colors)?;
+	if cache.is_none() {
+		let _ = fs::remove_file(frame);
+	}
+	Ok(())
+}
+
+/// `title` and as much of `description` as fits in a box of `size` cells.
+fn text_card(
+	title: &str,
+	description: &str,
+	(width, height): (u32, u32),
+) -> String {
+	let inner = (width as usize).saturating_sub(4).max(10);
+	let mut lines = wrap(title, inner);
+	lines.push(String::new());
+	lines.extend(wrap(description, inner));
+	if lines.last().is_some_and(String::is_empty) {
+		lines.pop();
+	}
+	let rows = (height as usize).saturating_sub(2).max(1);
+	if lines.len() > rows {
+		lines.truncate(rows);
+		let last = &mut lines[rows - 1];
+		if last.chars().count() == inner {
+			last.pop();
+		}
+		last.push('…');
+	}
+
+	let mut card = format!("┌{}┐\n", "─".repeat(inner + 2));
+	for line in lines {
+		let padding = inner - line.chars().count();
+		card += &format!("│ {}{} │\n", line, " ".repeat(padding));
+	}
+	card += &format!("└{}┘\n", "─".repeat(inner + 2));
+	card
+}
+
+/// Breaks `text` into lines of at most `width` characters at spaces, and
+/// anywhere in words that are longer.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+	let mut lines = Vec::new();
+	for paragraph in text.lines() {
+		let mut line = String::new();
+		for word in paragraph.split_whitespace() {
+			let mut word = word.chars().collect::<Vec<_>>();
+			while word.len() > width {
+				if !line.is_empty() {
+					lines.push(std::mem::take(&mut line));
+				}
+				lines.push(word.drain(..width).collect());
+			}
+			let length = line.chars().count();
+			if length > 0 && length + 1 + word.len() > width {
+				lines.push(std::mem::take(&mut line));
+			}
+			if !line.is_empty() {
+				line.push(' ');
+			}
+			line.extend(word);
+		}
+		lines.push(line);
+	}
+	lines
+}
+
+/// Renders the JPG, or the first frame of the GIF or MP4.
+///
+/// Previews are optional, so missing tools only print a note. Frames are
+/// cached in [`config::cache_dir`] unless `cache` is false.
+pub fn show_preview(
+	dir: &Path,
+	previewer: Option<Previewer>,
+	size: Option<(u32, u32)>,
+	cache: bool,
+) -> Result<()> {
+	let gif = dir.join("preview.gif");
+	let jpg = dir.join("preview.jpg");
+	let mp4 = dir.join("preview.mp4");
+
+	let Some(colors) = Colors::detect() else {
+		info!("No previews on a terminal without TERM or with TERM=dumb.");
+		return Ok(());
+	};
+	let size = size.unwrap_or_else(detect_size);
+	if !jpg.exists() && !gif.exists() && !mp4.exists() {
+		// Still something to recognize the wallpaper by.
+		let info = read_project_info(dir).unwrap_or_default();
+		let name = dir.file_name().unwrap_or_default().to_string_lossy();
+		let title = info.title.unwrap_or_else(|| name.to_string());
+		let description = info.description.unwrap_or_default();
+		print!("{}", text_card(&title, &description, size));
+		return Ok(());
+	}
+	let Some(previewer) = previewer.or_else(Previewer::detect) else {
+		info!("Install `chafa` or `viu` to see previews.");
+		return Ok(());
+	};
+	if let Err(e) = previewer.binary().path() {
+		info!("{}", e);
+		return Ok(());
+	}
+
+	let cache = if cache { cache_path(dir) } else { None };
+	if jpg.exists() {
+		show_image(previewer, &jpg, size, colors)?;
+	} else if gif.exists() {
+		show_frame(previewer, size, colors, &gif, cache, first_frame)?;
+	} else if mp4.exists() {
+		if let Err(e) = FFMPEG_BIN.path() {
+			info!("{}", e);
+			return Ok(());
+		}
+		show_frame(previewer, size, colors, &mp4, cache, video_frame)?;
+	}
+
+	Ok(())
+}
@@ -0,0 +1,132 @@
+//! A wallpaper's `project.json`.
+
+use std::{fs, io, path::Path};
+
+use log::warn;
+use serde::{Deserialize, Deserializer, de::DeserializeOwned};
+use serde_json::Value;
+
+/// The parts of a wallpaper's `project.json` this tool cares about.
+///
+/// Every field is lenient, so one of the wrong type is left out instead of
+/// failing the whole file.
+#[derive(Debug, Default, Deserialize)]
+pub struct ProjectInfo {
+	/// The name shown in Wallpaper Engine and the Workshop.
+	#[serde(default, deserialize_with = "lenient")]
+	pub title: Option<String>,
+	/// The Workshop description.
+	#[serde(default, deserialize_with = "lenient")]
+	pub description: Option<String>,
+	/// `scene`, `video`, `web` or `application`.
+	#[serde(rename = "type", default, deserialize_with = "lenient")]
+	pub kind: Option<String>,
+	/// Workshop tags, e.g. `Nature`.
+	#[serde(default, deserialize_with = "lenient")]
+	pub tags: Option<Vec<String>>,
+	/// `Everyone`, `Questionable` or `Mature`.
+	#[serde(default, deserialize_with = "lenient")]
+	pub contentrating: Option<String>,
+	/// The file the wallpaper runs, relative to its folder.
+	#[serde(default, deserialize_with = "lenient")]
+	pub file: Option<String>,
+	/// The resolution some wallpapers say they were made for.
+	#[serde(default, deserialize_with = "lenient")]
+	pub width: Option<u32>,
+	/// Goes with `width`.
+	#[serde(default, deserialize_with = "lenient")]
+	pub height: Option<u32>,
+}
+
+/// Ignores a malformed field instead of failing the whole file.
+fn lenient<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+	D: Deserializer<'de>,
+	T: DeserializeOwned,
+{
+	let value = Value::deserialize(deserializer)?;
+	Ok(serde_json::from_value(value).ok())
+}
+
+/// What renders a wallpaper, from the `type` field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WallpaperType {
+	/// Made in Wallpaper Engine's editor.
+	Scene,
+	/// A video file.
+	Video,
+	/// A web page, which needs the browser.
+	Web,
+	/// A program of its own.
+	Application,
+	/// No `type`, or one this tool doesn't know.
+	Unknown,
+}
+impl ProjectInfo {
+	/// The designed resolution, if it has one.
+	pub fn size(&self) -> Option<(u32, u32)> {
+		match (self.width, self.height) {
+			(Some(width), Some(height)) if width > 0 && height > 0 => {
+				Some((width, height))
+			}
+			_ => None,
+		}
+	}
+
+	/// The `type` field, ignoring case.
+	pub fn wallpaper_type(&self) -> WallpaperType {
+		match self.kind.as_deref().map(str::to_lowercase).as_deref() {
+			Some("scene") => WallpaperType::Scene,
+			Some("video") => WallpaperType::Video,
+			Some("web") => WallpaperType::Web,
+			Some("application") => WallpaperType::Application,
+			_ => WallpaperType::Unknown,
+		}
+	}
+}
+
+/// Reads `project.json`, warning about files that exist but can't be
+/// understood at all.
+pub fn read_project_info(dir: &Path) -> Option<ProjectInfo> {
+	let path = dir.join("project.json");
+	let id = dir.file_name().unwrap_or_default().to_string_lossy();
+	let bytes = match fs::read(&path) {
+		Ok(bytes) => bytes,
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+		Err(e) => {
+			warn!("Couldn't read the project.json of {}: {}", id, e);
+			return None;
+		}
+	};
+	// Some have invalid UTF-8, a BOM or junk after the object.
+	let content = String::from_utf8_lossy(&bytes);
+	let content = content.trim_start_matches('\u{feff}');
+	let value = serde_json::Deserializer::from_str(content)
+		.into_iter::<Value>()
+		.next();
+	let result = match value {
+		Some(Ok(value)) if value.is_object() => {
+			serde_json::from_value(value).map_err(|e| e.to_string())
+		}
+		Some(Ok(_)) => Err("not a JSON object".to_string()),
+		Some(Err(e)) => Err(e.to_string()),
+		None => Err("empty file".to_string()),
+	};
+	result
+		.map_err(|e| {
+			warn!("Couldn't parse the project.json of {}: {}", id, e)
+		})
+		.ok()
+}
+
+/// The largest size with the aspect ratio of `size` that fits in `bounds`.
+pub fn fit_aspect(size: (u32, u32), bounds: (u32, u32)) -> (u32, u32) {
+	let (width, height) = (u64::from(size.0), u64::from(size.1));
+	let (max_width, max_height) = (u64::from(bounds.0), u64::from(bounds.1));
+	// Compare the ratios without floats: w / h > W / H.
+	if width * max_height > max_width * height {
+		(bounds.0, (max_width * height / width) as u32)
+	} else {
+		((max_height * width / height) as u32, bounds.1)
+	}
+}
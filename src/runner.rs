@@ -0,0 +1,245 @@
+//! Runs the external programs, or records them in tests.
+
+use std::{
+	cell::RefCell,
+	io::{self, Write},
+	path::PathBuf,
+	process::{Command, ExitStatus, Output, Stdio},
+	rc::Rc,
+	time::Duration,
+};
+
+use sysinfo::{ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind};
+use which::which;
+
+use crate::wait_until;
+
+/// Runs every external program and looks at the running processes, so
+/// tests can swap in one that only records the commands.
+pub trait CommandRunner {
+	/// Finds `name` on PATH.
+	fn which(&self, name: &str) -> Option<PathBuf>;
+	/// Runs `command` to completion.
+	fn status(&self, command: &mut Command) -> io::Result<ExitStatus>;
+	/// Like [`CommandRunner::status`], but kills `command` and returns
+	/// `None` once it takes longer than `timeout`.
+	fn status_timeout(
+		&self,
+		command: &mut Command,
+		timeout: Duration,
+	) -> io::Result<Option<ExitStatus>>;
+	/// Starts `command` without waiting for it.
+	fn spawn(&self, command: &mut Command) -> io::Result<()>;
+	/// Runs `command` to completion, capturing stdout and stderr.
+	fn output(&self, command: &mut Command) -> io::Result<Output>;
+	/// Like [`CommandRunner::output`], with `input` on its stdin, which
+	/// other users can't read like they can the arguments.
+	fn output_with_input(
+		&self,
+		command: &mut Command,
+		input: &[u8],
+	) -> io::Result<Output>;
+	/// Whether a process with one of these executable names is running.
+	///
+	/// Only the name and `argv[0]` are compared, so a command line that
+	/// merely mentions one (like ours) doesn't count.
+	fn process_running(&self, names: &[&str]) -> bool;
+}
+
+/// Actually runs everything.
+pub struct SystemRunner;
+impl CommandRunner for SystemRunner {
+	fn which(&self, name: &str) -> Option<PathBuf> {
+		which(name).ok()
+	}
+
+	fn status(&self, command: &mut Command) -> io::Result<ExitStatus> {
+		command.status()
+	}
+
+	fn status_timeout(
+		&self,
+		command: &mut Command,
+		timeout: Duration,
+	) -> io::Result<Option<ExitStatus>> {
+		let mut child = command.spawn()?;
+		let mut status = None;
+		if !wait_until(
+			|| {
+				status = child.try_wait().ok().flatten();
+				status.is_some()
+			},
+			timeout,
+		) {
+			let _ = child.kill();
+			let _ = child.wait();
+		}
+		Ok(status)
+	}
+
+	// Steam and the engine are meant to outlive us, so nothing waits on them.
+	#[allow(clippy::zombie_processes)]
+	fn spawn(&self, command: &mut Command) -> io::Result<()> {
+		command.spawn().map(drop)
+	}
+
+	fn output(&self, command: &mut Command) -> io::Result<Output> {
+		command.output()
+	}
+
+	fn output_with_input(
+		&self,
+		command: &mut Command,
+		input: &[u8],
+	) -> io::Result<Output> {
+		let mut child = command
+			.stdin(Stdio::piped())
+			.stdout(Stdio::piped())
+			.stderr(Stdio::piped())
+			.spawn()?;
+		// Dropped right after, so the program sees the end of it.
+		if let Some(mut stdin) = child.stdin.take() {
+			stdin.write_all(input)?;
+		}
+		child.wait_with_output()
+	}
+
+	fn process_running(&self, names: &[&str]) -> bool {
+		let mut system = System::new();
+		system.refresh_processes_specifics(
+			ProcessesToUpdate::All,
+			true,
+			ProcessRefreshKind::nothing().with_cmd(UpdateKind::OnlyIfNotSet),
+		);
+		system.processes().values().any(|process| {
+			// Wine sets `argv[0]` to the Windows path of the executable.
+			let argv0 =
+				process.cmd().first().map(|arg| arg.to_string_lossy());
+			let argv0 = argv0
+				.as_deref()
+				.and_then(|arg| arg.rsplit(['/', '\\']).next());
+			names
+				.iter()
+				.any(|name| process.name() == *name || argv0 == Some(*name))
+		})
+	}
+}
+
+thread_local! {
+	static RUNNER: RefCell<Rc<dyn CommandRunner>> =
+		RefCell::new(Rc::new(SystemRunner));
+}
+
+/// The runner of the current thread, [`SystemRunner`] unless
+/// [`set_command_runner`] changed it.
+pub fn command_runner() -> Rc<dyn CommandRunner> {
+	RUNNER.with(|runner| runner.borrow().clone())
+}
+
+/// Replaces the runner of the current thread.
+pub fn set_command_runner(runner: Rc<dyn CommandRunner>) {
+	RUNNER.with(|current| *current.borrow_mut() = runner);
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use std::{os::unix::process::ExitStatusExt, rc::Rc};
+
+	use super::*;
+
+	/// Records every command's argv instead of running it, with programs
+	/// found on PATH as just their name.
+	///
+	/// Queries find nothing and no process is running, like on a machine
+	/// without Wallpaper Engine or a compositor.
+	#[derive(Default)]
+	pub(crate) struct RecordingRunner {
+		commands: RefCell<Vec<Vec<String>>>,
+		queries: RefCell<Vec<Vec<String>>>,
+	}
+	impl RecordingRunner {
+		/// Installs a new one for the current thread.
+		pub(crate) fn install() -> Rc<RecordingRunner> {
+			let runner = Rc::new(RecordingRunner::default());
+			set_command_runner(runner.clone());
+			runner
+		}
+
+		/// The commands that were run or started.
+		pub(crate) fn commands(&self) -> Vec<Vec<String>> {
+			self.commands.borrow().clone()
+		}
+
+		/// The commands whose output was asked for.
+		pub(crate) fn queries(&self) -> Vec<Vec<String>> {
+			self.queries.borrow().clone()
+		}
+
+		fn argv(command: &Command) -> Vec<String> {
+			[command.get_program()]
+				.into_iter()
+				.chain(command.get_args())
+				.map(|arg| arg.to_string_lossy().to_string())
+				.collect()
+		}
+
+		fn record(&self, command: &Command) {
+			self.commands.borrow_mut().push(Self::argv(command));
+		}
+	}
+	impl CommandRunner for RecordingRunner {
+		fn which(&self, name: &str) -> Option<PathBuf> {
+			Some(PathBuf::from(name))
+		}
+
+		fn status(&self, command: &mut Command) -> io::Result<ExitStatus> {
+			self.record(command);
+			Ok(ExitStatus::from_raw(0))
+		}
+
+		fn status_timeout(
+			&self,
+			command: &mut Command,
+			_: Duration,
+		) -> io::Result<Option<ExitStatus>> {
+			self.record(command);
+			Ok(Some(ExitStatus::from_raw(0)))
+		}
+
+		fn spawn(&self, command: &mut Command) -> io::Result<()> {
+			self.record(command);
+			Ok(())
+		}
+
+		fn output_with_input(
+			&self,
+			command: &mut Command,
+			_: &[u8],
+		) -> io::Result<Output> {
+			self.output(command)
+		}
+
+		fn output(&self, command: &mut Command) -> io::Result<Output> {
+			self.queries.borrow_mut().push(Self::argv(command));
+			// What `xdotool search` and friends say when nothing matches.
+			Ok(Output {
+				status: ExitStatus::from_raw(1 << 8),
+				stdout: Vec::new(),
+				stderr: Vec::new(),
+			})
+		}
+
+		fn process_running(&self, _: &[&str]) -> bool {
+			false
+		}
+	}
+
+	#[test]
+	fn records_argv() {
+		let runner = RecordingRunner::install();
+		command_runner()
+			.status(Command::new("steam").args(["+app_stop", "431960"]))
+			.unwrap();
+		assert_eq!(runner.commands(), [["steam", "+app_stop", "431960"]]);
+	}
+}
@@ -0,0 +1,229 @@
+//! Valve's KeyValues format, which Steam's config files use.
+
+use std::{fs, path::Path};
+
+use anyhow::{Result, anyhow};
+
+/// A node from Valve's KeyValues text format (`.vdf`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Vdf {
+	/// A quoted string.
+	Value(String),
+	/// A `{}` block, its pairs in file order.
+	Object(Vec<(String, Vdf)>),
+}
+impl Vdf {
+	/// Parses a whole file's worth of `"key" "value"` pairs and `"key" {}`
+	/// blocks into one object.
+	pub fn parse(input: &str) -> Result<Vdf> {
+		let mut tokens = tokenize(input)?.into_iter().peekable();
+		let object = parse_object(&mut tokens)?;
+		if tokens.next().is_some() {
+			return Err(anyhow!("unexpected '}}'"));
+		}
+		Ok(Vdf::Object(object))
+	}
+
+	/// [`Vdf::parse`] on a file's contents.
+	pub fn from_file(path: &Path) -> Result<Vdf> {
+		let content = fs::read_to_string(path)
+			.map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+		Vdf::parse(&content).map_err(|e| anyhow!("{}: {}", path.display(), e))
+	}
+
+	/// Looks up a child by key, ignoring case like Steam does.
+	pub fn get(&self, key: &str) -> Option<&Vdf> {
+		self.entries()
+			.iter()
+			.find(|(k, _)| k.eq_ignore_ascii_case(key))
+			.map(|(_, v)| v)
+	}
+
+	/// Follows a path of keys, e.g. `["libraryfolders", "0", "path"]`.
+	pub fn get_path(&self, keys: &[&str]) -> Option<&Vdf> {
+		keys.iter().try_fold(self, |vdf, key| vdf.get(key))
+	}
+
+	/// The string of a plain value, or nothing for an object.
+	pub fn as_str(&self) -> Option<&str> {
+		match self {
+			Vdf::Value(value) => Some(value),
+			Vdf::Object(_) => None,
+		}
+	}
+
+	/// The children of an object, or nothing for a plain value.
+	pub fn entries(&self) -> &[(String, Vdf)] {
+		match self {
+			Vdf::Value(_) => &[],
+			Vdf::Object(entries) => entries,
+		}
+	}
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+	String(String),
+	Open,
+	Close,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+	let mut tokens = Vec::new();
+	let mut chars = input.chars().peekable();
+	while let Some(c) = chars.next() {
+		match c {
+			'{' => tokens.push(Token::Open),
+			'}' => tokens.push(Token::Close),
+			'"' => {
+				let mut string = String::new();
+				loop {
+					match chars.next() {
+						Some('"') => break,
+						Some('\\') => match chars.next() {
+							Some('n') => string.push('\n'),
+							Some('t') => string.push('\t'),
+							Some(c) => string.push(c),
+							None => {
+								return Err(anyhow!("unterminated string"));
+							}
+						},
+						Some(c) => string.push(c),
+						None => return Err(anyhow!("unterminated string")),
+					}
+				}
+				tokens.push(Token::String(string));
+			}
+			'/' if chars.peek() == Some(&'/') => {
+				for c in chars.by_ref() {
+					if c == '\n' {
+						break;
+					}
+				}
+			}
+			// Platform conditionals like `[$WIN32]` don't matter here.
+			'[' => {
+				for c in chars.by_ref() {
+					if c == ']' {
+						break;
+					}
+				}
+			}
+			c if c.is_whitespace() => {}
+			c => {
+				let mut string = c.to_string();
+				while let Some(&c) = chars.peek() {
+					if c.is_whitespace() || matches!(c, '{' | '}' | '"') {
+						break;
+					}
+					string.push(c);
+					chars.next();
+				}
+				tokens.push(Token::String(string));
+			}
+		}
+	}
+	Ok(tokens)
+}
+
+fn parse_object(
+	tokens: &mut std::iter::Peekable<std::vec::IntoIter<Token>>,
+) -> Result<Vec<(String, Vdf)>> {
+	let mut entries = Vec::new();
+	loop {
+		let key = match tokens.peek() {
+			None | Some(Token::Close) => return Ok(entries),
+			Some(Token::Open) => {
+				return Err(anyhow!("expected a key, got '{{'"));
+			}
+			Some(Token::String(_)) => match tokens.next() {
+				Some(Token::String(key)) => key,
+				_ => unreachable!(),
+			},
+		};
+		let value = match tokens.next() {
+			Some(Token::String(value)) => Vdf::Value(value),
+			Some(Token::Open) => {
+				let object = parse_object(tokens)?;
+				if tokens.next() != Some(Token::Close) {
+					return Err(anyhow!("missing '}}' for {:?}", key));
+				}
+				Vdf::Object(object)
+			}
+			Some(Token::Close) | None => {
+				return Err(anyhow!("missing value for {:?}", key));
+			}
+		};
+		entries.push((key, value));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn nested_blocks() {
+		let vdf = Vdf::parse(
+			r#""libraryfolders"
+			{
+				"0"
+				{
+					"path"		"/home/me/.local/share/Steam"
+					"apps" { "431960" "123" }
+				}
+				"1" { "path" "/mnt/games" }
+			}"#,
+		)
+		.unwrap();
+		assert_eq!(
+			vdf.get_path(&["LibraryFolders", "0", "path"])
+				.and_then(Vdf::as_str),
+			Some("/home/me/.local/share/Steam")
+		);
+		assert_eq!(
+			vdf.get_path(&["libraryfolders", "0", "apps", "431960"])
+				.and_then(Vdf::as_str),
+			Some("123")
+		);
+		assert_eq!(vdf.get("libraryfolders").unwrap().entries().len(), 2);
+		assert!(
+			vdf.get_path(&["libraryfolders", "1"])
+				.unwrap()
+				.as_str()
+				.is_none()
+		);
+	}
+
+	#[test]
+	fn escapes_comments_and_conditionals() {
+		let vdf = Vdf::parse(
+			r#"// Written by Steam
+			"tool"
+			{
+				"display_name" "Proton \"Custom\"\tEdition" // trailing
+				"path" "C:\\Games"
+				"only_windows" "1" [$WIN32]
+				unquoted value
+			}"#,
+		)
+		.unwrap();
+		let tool = vdf.get("tool").unwrap();
+		assert_eq!(
+			tool.get("display_name").and_then(Vdf::as_str),
+			Some("Proton \"Custom\"\tEdition")
+		);
+		assert_eq!(tool.get("path").and_then(Vdf::as_str), Some(r"C:\Games"));
+		assert_eq!(tool.get("only_windows").and_then(Vdf::as_str), Some("1"));
+		assert_eq!(tool.get("unquoted").and_then(Vdf::as_str), Some("value"));
+	}
+
+	#[test]
+	fn malformed_input() {
+		assert!(Vdf::parse(r#""key" "unterminated"#).is_err());
+		assert!(Vdf::parse(r#""block" { "key" "value""#).is_err());
+		assert!(Vdf::parse(r#""key""#).is_err());
+		assert!(Vdf::parse(r#""key" "value" }"#).is_err());
+		assert!(Vdf::parse("{ }").is_err());
+	}
+}
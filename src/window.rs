@@ -0,0 +1,296 @@
+//! Finding and arranging the wallpaper windows through the compositor.
+
+use std::{env, process::Command, sync::OnceLock};
+
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use serde_json::Value;
+
+use crate::{Binary, XDOTOOL_BIN, command_runner};
+
+pub(crate) static HYPRCTL_BIN: Binary = Binary::new("hyprctl");
+pub(crate) static SWAYMSG_BIN: Binary = Binary::new("swaymsg");
+
+/// A way to find out which windows are open.
+pub trait WindowBackend: Sync {
+	/// Whether a window is named exactly `title`.
+	fn title_exists(&self, title: &str) -> bool;
+	/// Whether a window has the X11 class (or Wayland app ID) `class`.
+	fn class_exists(&self, class: &str) -> bool;
+	/// The program it asks.
+	fn binary(&self) -> &'static Binary;
+	/// Makes windows of the X11 class `class` opened from now on part of the
+	/// desktop background instead of regular windows.
+	fn use_as_background(&self, class: &str) -> Result<()> {
+		let _ = class;
+		Err(anyhow!(
+			"--layer background needs sway, or Hyprland with the \
+			 hyprwinwrap plugin"
+		))
+	}
+	/// Stacks the window named `title` below the others and keeps it from
+	/// taking focus.
+	fn send_to_back(&self, title: &str) -> Result<()> {
+		let _ = title;
+		Err(anyhow!(
+			"only Hyprland and sway can send a window to the back"
+		))
+	}
+}
+
+/// Asks the X server, which only sees X11 and Xwayland windows.
+pub struct Xdotool;
+impl Xdotool {
+	fn search(by: &str, pattern: &str) -> bool {
+		let Ok(xdotool) = XDOTOOL_BIN.path() else {
+			return false;
+		};
+		// Captured, the IDs it prints would mix with our output.
+		command_runner()
+			.output(Command::new(xdotool).args(["search", by, pattern]))
+			.is_ok_and(|output| output.status.success())
+	}
+}
+impl WindowBackend for Xdotool {
+	fn title_exists(&self, title: &str) -> bool {
+		Xdotool::search("--name", &exact_title_pattern(title))
+	}
+
+	fn class_exists(&self, class: &str) -> bool {
+		Xdotool::search("--class", class)
+	}
+
+	fn binary(&self) -> &'static Binary {
+		&XDOTOOL_BIN
+	}
+}
+
+/// An xdotool `--name` pattern matching exactly `title`, since it's
+/// otherwise a regex that `Wallpaper #1` would match `Wallpaper #10` with.
+fn exact_title_pattern(title: &str) -> String {
+	let mut pattern = String::from("^");
+	for c in title.chars() {
+		// POSIX extended regex metacharacters.
+		if r".[]()*+?{}|^$\".contains(c) {
+			pattern.push('\\');
+		}
+		pattern.push(c);
+	}
+	pattern.push('$');
+	pattern
+}
+
+/// Runs `binary` and parses its stdout as JSON.
+fn query_json(binary: &Binary, args: &[&str]) -> Option<Value> {
+	let output = command_runner()
+		.output(Command::new(binary.path().ok()?).args(args))
+		.ok()?;
+	if !output.status.success() {
+		return None;
+	}
+	serde_json::from_slice(&output.stdout).ok()
+}
+
+/// Asks Hyprland through `hyprctl clients`.
+pub struct Hyprland;
+impl Hyprland {
+	fn find_client(field: &str, value: &str) -> Option<Value> {
+		let clients = query_json(&HYPRCTL_BIN, &["clients", "-j"])?;
+		clients
+			.as_array()?
+			.iter()
+			.find(|client| client[field].as_str() == Some(value))
+			.cloned()
+	}
+
+	/// Runs `hyprctl` with `args`, which exits with 0 either way and prints
+	/// `ok` for every command that worked.
+	fn run(args: &[&str]) -> Result<()> {
+		let output = command_runner()
+			.output(Command::new(HYPRCTL_BIN.path()?).args(args))?;
+		let reply = String::from_utf8_lossy(&output.stdout);
+		match reply.split_whitespace().find(|reply| *reply != "ok") {
+			None if !reply.trim().is_empty() => Ok(()),
+			_ => Err(anyhow!("hyprctl said: {}", reply.trim())),
+		}
+	}
+}
+impl WindowBackend for Hyprland {
+	fn title_exists(&self, title: &str) -> bool {
+		Hyprland::find_client("title", title).is_some()
+	}
+
+	fn class_exists(&self, class: &str) -> bool {
+		Hyprland::find_client("class", class).is_some()
+	}
+
+	fn binary(&self) -> &'static Binary {
+		&HYPRCTL_BIN
+	}
+
+	/// hyprwinwrap puts windows of its configured class behind everything,
+	/// sized to their monitor.
+	fn use_as_background(&self, class: &str) -> Result<()> {
+		Hyprland::run(&["keyword", "plugin:hyprwinwrap:class", class])
+			.map_err(|e| {
+				anyhow!(
+					"couldn't configure hyprwinwrap, is the plugin \
+					 loaded? {}",
+					e
+				)
+			})
+	}
+
+	fn send_to_back(&self, title: &str) -> Result<()> {
+		// Addresses need no escaping, unlike a title regex.
+		let address = Hyprland::find_client("title", title)
+			.and_then(|client| client["address"].as_str().map(str::to_string))
+			.ok_or_else(|| anyhow!("no window named {:?}", title))?;
+		Hyprland::run(&[
+			"--batch",
+			&format!(
+				"dispatch alterzorder bottom,address:{0} ; dispatch setprop \
+				 address:{0} nofocus 1",
+				address
+			),
+		])
+	}
+}
+
+/// Asks sway through `swaymsg -t get_tree`.
+pub struct Sway;
+impl Sway {
+	fn find_node(matches: impl Fn(&Value) -> bool) -> Option<Value> {
+		fn walk(
+			node: &Value,
+			matches: &dyn Fn(&Value) -> bool,
+		) -> Option<Value> {
+			if matches(node) {
+				return Some(node.clone());
+			}
+			["nodes", "floating_nodes"].iter().find_map(|key| {
+				node[key]
+					.as_array()
+					.into_iter()
+					.flatten()
+					.find_map(|child| walk(child, matches))
+			})
+		}
+		let tree = query_json(&SWAYMSG_BIN, &["-t", "get_tree", "-r"])?;
+		walk(&tree, &matches)
+	}
+
+	/// Containers have names too, only windows have a PID.
+	fn find_window(title: &str) -> Option<Value> {
+		Sway::find_node(|node| {
+			node["pid"].is_u64() && node["name"].as_str() == Some(title)
+		})
+	}
+
+	/// Runs sway commands, `swaymsg` exits with an error when one fails.
+	fn run(command: &str) -> Result<()> {
+		let output = command_runner()
+			.output(Command::new(SWAYMSG_BIN.path()?).arg(command))?;
+		if !output.status.success() {
+			let reply = String::from_utf8_lossy(&output.stdout);
+			return Err(anyhow!("swaymsg said: {}", reply.trim()));
+		}
+		Ok(())
+	}
+}
+impl WindowBackend for Sway {
+	fn title_exists(&self, title: &str) -> bool {
+		Sway::find_window(title).is_some()
+	}
+
+	fn class_exists(&self, class: &str) -> bool {
+		Sway::find_node(|node| {
+			node["window_properties"]["class"].as_str() == Some(class)
+				|| node["app_id"].as_str() == Some(class)
+		})
+		.is_some()
+	}
+
+	fn binary(&self) -> &'static Binary {
+		&SWAYMSG_BIN
+	}
+
+	/// sway can't stack below tiled windows, so they're tiled and kept from
+	/// taking focus when they open.
+	fn use_as_background(&self, class: &str) -> Result<()> {
+		let criteria = format!("[class=\"^{}$\"]", class);
+		Sway::run(&format!(
+			"no_focus {0}; for_window {0} floating disable",
+			criteria
+		))
+	}
+
+	/// Floating windows stay above tiled ones, so it's tiled in case the
+	/// rule from [`Sway::use_as_background`] missed it.
+	fn send_to_back(&self, title: &str) -> Result<()> {
+		// IDs need no escaping, unlike a title regex.
+		let id = Sway::find_window(title)
+			.and_then(|node| node["id"].as_u64())
+			.ok_or_else(|| anyhow!("no window named {:?}", title))?;
+		Sway::run(&format!("[con_id={}] floating disable", id))
+	}
+}
+
+/// Which [`WindowBackend`] to use.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum WindowBackendKind {
+	/// The compositor's own IPC when it's supported, xdotool otherwise.
+	Auto,
+	/// `xdotool`, through Xwayland.
+	Xdotool,
+	/// `hyprctl`.
+	Hyprland,
+	/// `swaymsg`.
+	Sway,
+}
+impl WindowBackendKind {
+	/// Without `auto`, detected from the variables the compositors set.
+	fn resolve(self) -> WindowBackendKind {
+		if self != WindowBackendKind::Auto {
+			return self;
+		}
+		let set =
+			|key| env::var_os(key).is_some_and(|value| !value.is_empty());
+		if set("HYPRLAND_INSTANCE_SIGNATURE") && HYPRCTL_BIN.path().is_ok() {
+			WindowBackendKind::Hyprland
+		} else if set("SWAYSOCK") && SWAYMSG_BIN.path().is_ok() {
+			WindowBackendKind::Sway
+		} else {
+			WindowBackendKind::Xdotool
+		}
+	}
+}
+
+/// Where wallpaper windows end up.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Layer {
+	/// A regular window, wherever the compositor puts it.
+	Window,
+	/// Behind every other window, see [`WindowBackend::use_as_background`].
+	Background,
+}
+
+static BACKEND: OnceLock<WindowBackendKind> = OnceLock::new();
+
+/// The backend everything uses, detected unless [`set_window_backend`]
+/// picked one.
+pub fn window_backend() -> &'static dyn WindowBackend {
+	match BACKEND.get_or_init(|| WindowBackendKind::Auto.resolve()) {
+		WindowBackendKind::Hyprland => &Hyprland,
+		WindowBackendKind::Sway => &Sway,
+		WindowBackendKind::Auto | WindowBackendKind::Xdotool => &Xdotool,
+	}
+}
+
+/// Picks the backend, which only works before anything used it.
+pub fn set_window_backend(kind: WindowBackendKind) -> Result<()> {
+	BACKEND.set(kind.resolve()).map_err(|_| {
+		anyhow!("the window backend was already used, set it earlier")
+	})?;
+	Ok(())
+}